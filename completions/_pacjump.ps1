@@ -21,16 +21,57 @@ Register-ArgumentCompleter -Native -CommandName 'pacjump' -ScriptBlock {
 
     $completions = @(switch ($command) {
         'pacjump' {
-            [CompletionResult]::new('--recurse', '--recurse', [CompletionResultType]::ParameterName, 'Recursively query the dependencies of the given package; implies ''--all''')
+            [CompletionResult]::new('--recurse', '--recurse', [CompletionResultType]::ParameterName, 'Recursively query the dependencies of the given package; implies `--all`')
+            [CompletionResult]::new('--reverse-recurse', '--reverse-recurse', [CompletionResultType]::ParameterName, 'Recursively query the packages that depend on the given package, i.e. the reverse of `--recurse`; only the installed dependents are followed, unless `--sync`')
+            [CompletionResult]::new('--why', '--why', [CompletionResultType]::ParameterName, 'Explain why the given package is installed, by the chains of dependencies from the explicitly installed packages down to it; one of the shortest chains is shown for each explicit package')
+            [CompletionResult]::new('--format', '--format', [CompletionResultType]::ParameterName, 'The output format of the packages; `dot` and `tree` require `--recurse`')
+            [CompletionResult]::new('--max-depth', '--max-depth', [CompletionResultType]::ParameterName, 'Truncate the `tree` output below the given depth')
+            [CompletionResult]::new('--columns', '--columns', [CompletionResultType]::ParameterName, 'The columns of the `csv` and `tsv` formats, separated by commas, e.g. `name,version,depends_on.name`; by default all top-level fields except `sync_with`')
+            [CompletionResult]::new('--list-separator', '--list-separator', [CompletionResultType]::ParameterName, 'The separator to join lists, e.g. `licenses`, within a single cell of the `csv` and `tsv` formats; a space by default')
+            [CompletionResult]::new('--fields', '--fields', [CompletionResultType]::ParameterName, 'Output only the selected fields, separated by commas; nested fields are selected by their paths, e.g. `name,sync_with.version`')
+            [CompletionResult]::new('--config', '--config', [CompletionResultType]::ParameterName, 'Read an alternate pacman configuration file')
+            [CompletionResult]::new('--root', '--root', [CompletionResultType]::ParameterName, 'Inspect an alternate installation root, e.g. a chroot or a mounted image; overrides `RootDir` in the configuration')
+            [CompletionResult]::new('--dbpath', '--dbpath', [CompletionResultType]::ParameterName, 'Read the databases from an alternate location; overrides `DBPath` in the configuration')
+            [CompletionResult]::new('--from-dump', '--from-dump', [CompletionResultType]::ParameterName, 'Read the packages from a JSON dump of `pacjump`, instead of the pacman databases; the reverse dependencies are regenerated from the packages in the dump')
             [CompletionResult]::new('--sync', '--sync', [CompletionResultType]::ParameterName, 'Query the sync databases; by default only the local database (of currently installed packages) is queried')
             [CompletionResult]::new('--all', '--all', [CompletionResultType]::ParameterName, 'Query all packages, including those not explicitly installed; by default only explicitly installed packages are shown')
             [CompletionResult]::new('--plain', '--plain', [CompletionResultType]::ParameterName, 'Output package info from the current database only; by default we enrich the output by combining information from both the local and the sync databases')
-            [CompletionResult]::new('--optional', '--optional', [CompletionResultType]::ParameterName, '''--recurse'' installed optional dependencies as well')
-            [CompletionResult]::new('--summary', '--summary', [CompletionResultType]::ParameterName, '''--recurse'' dependencies, but only prints package names and versions')
+            [CompletionResult]::new('--all-paths', '--all-paths', [CompletionResultType]::ParameterName, 'Show all the chains found by `--why`, instead of the shortest ones')
+            [CompletionResult]::new('--foreign', '--foreign', [CompletionResultType]::ParameterName, 'Query only the installed packages that are not found in the sync databases, e.g. those from the AUR or built locally; the same as `pacman -Qm`')
+            [CompletionResult]::new('--native', '--native', [CompletionResultType]::ParameterName, 'Query only the installed packages that are found in the sync databases; the same as `pacman -Qn`')
+            [CompletionResult]::new('--orphans', '--orphans', [CompletionResultType]::ParameterName, 'Report the orphans, i.e. the packages installed as dependencies that are no longer required, including those only wanted optionally and the cycles of packages that only require each other')
+            [CompletionResult]::new('--upgradable', '--upgradable', [CompletionResultType]::ParameterName, 'List the installed packages that can be upgraded from the sync databases, as they are synced, with the old and new versions and the size changes; the same as `pacman -Qu`')
+            [CompletionResult]::new('--cycles', '--cycles', [CompletionResultType]::ParameterName, 'Report the dependency cycles, i.e. the groups of packages that depend on each other, with the dependency strings forming each cycle; the installed packages are inspected, or the sync packages with `--sync`')
+            [CompletionResult]::new('--check', '--check', [CompletionResultType]::ParameterName, 'Exit with an error if `--cycles` finds any cycle, e.g. to check a repository in CI')
+            [CompletionResult]::new('--optional', '--optional', [CompletionResultType]::ParameterName, '`--recurse` installed optional dependencies as well; for `--reverse-recurse` and `--why`, follow the packages that optionally depend on the given package as well; for `--cycles`, follow the optional dependencies as well')
+            [CompletionResult]::new('--build', '--build', [CompletionResultType]::ParameterName, '`--recurse` the build dependencies, i.e. the `makedepends` and `checkdepends` of the given package, besides its runtime dependencies; the runtime dependencies are followed below. This is best used with `--sync`, as the local database may lack the build dependencies')
+            [CompletionResult]::new('--summary', '--summary', [CompletionResultType]::ParameterName, '`--recurse` or `--reverse-recurse`, but only prints package names and versions; for `--why`, only prints the chains')
+            [CompletionResult]::new('--ndjson', '--ndjson', [CompletionResultType]::ParameterName, 'Output JSON Lines (NDJSON), one package per line, as they are generated; by default a single JSON array is printed at the end. This is a shorthand for `--format=ndjson`')
+            [CompletionResult]::new('--envelope', '--envelope', [CompletionResultType]::ParameterName, 'Wrap the `json` output in an envelope, with the schema version and the information on the inspected system')
+            [CompletionResult]::new('--print-schema', '--print-schema', [CompletionResultType]::ParameterName, 'Print the JSON Schema of the `json` output and exit')
+            [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help (see more with ''--help'')')
+            [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help (see more with ''--help'')')
+            [CompletionResult]::new('diff', 'diff', [CompletionResultType]::ParameterValue, 'Compare two JSON dumps of `pacjump`')
+            [CompletionResult]::new('help', 'help', [CompletionResultType]::ParameterValue, 'Print this message or the help of the given subcommand(s)')
+            break
+        }
+        'pacjump;diff' {
+            [CompletionResult]::new('--summary', '--summary', [CompletionResultType]::ParameterName, 'Print a human-readable summary, instead of the JSON report')
             [CompletionResult]::new('-h', '-h', [CompletionResultType]::ParameterName, 'Print help')
             [CompletionResult]::new('--help', '--help', [CompletionResultType]::ParameterName, 'Print help')
             break
         }
+        'pacjump;help' {
+            [CompletionResult]::new('diff', 'diff', [CompletionResultType]::ParameterValue, 'Compare two JSON dumps of `pacjump`')
+            [CompletionResult]::new('help', 'help', [CompletionResultType]::ParameterValue, 'Print this message or the help of the given subcommand(s)')
+            break
+        }
+        'pacjump;help;diff' {
+            break
+        }
+        'pacjump;help;help' {
+            break
+        }
     })
 
     $completions.Where{ $_.CompletionText -like "$wordToComplete*" } |
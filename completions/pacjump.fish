@@ -1,7 +1,69 @@
-complete -c pacjump -l recurse -d 'Recursively query the dependencies of the given package; implies \'--all\'' -r
-complete -c pacjump -l sync -d 'Query the sync databases; by default only the local database (of currently installed packages) is queried'
-complete -c pacjump -l all -d 'Query all packages, including those not explicitly installed; by default only explicitly installed packages are shown'
-complete -c pacjump -l plain -d 'Output package info from the current database only; by default we enrich the output by combining information from both the local and the sync databases'
-complete -c pacjump -l optional -d '\'--recurse\' installed optional dependencies as well'
-complete -c pacjump -l summary -d '\'--recurse\' dependencies, but only prints package names and versions'
-complete -c pacjump -s h -l help -d 'Print help'
+# Print an optspec for argparse to handle cmd's options that are independent of any subcommand.
+function __fish_pacjump_global_optspecs
+    string join \n sync all plain recurse= reverse-recurse= why= all-paths foreign native orphans upgradable cycles check optional build summary ndjson format= max-depth= envelope print-schema columns= list-separator= fields= config= root= dbpath= from-dump= h/help
+end
+
+function __fish_pacjump_needs_command
+    # Figure out if the current invocation already has a command.
+    set -l cmd (commandline -opc)
+    set -e cmd[1]
+    argparse -s (__fish_pacjump_global_optspecs) -- $cmd 2>/dev/null
+    or return
+    if set -q argv[1]
+        # Also print the command, so this can be used to figure out what it is.
+        echo $argv[1]
+        return 1
+    end
+    return 0
+end
+
+function __fish_pacjump_using_subcommand
+    set -l cmd (__fish_pacjump_needs_command)
+    test -z "$cmd"
+    and return 1
+    contains -- $cmd[1] $argv
+end
+
+complete -c pacjump -n "__fish_pacjump_needs_command" -l recurse -d 'Recursively query the dependencies of the given package; implies `--all`' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l reverse-recurse -d 'Recursively query the packages that depend on the given package, i.e. the reverse of `--recurse`; only the installed dependents are followed, unless `--sync`' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l why -d 'Explain why the given package is installed, by the chains of dependencies from the explicitly installed packages down to it; one of the shortest chains is shown for each explicit package' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l format -d 'The output format of the packages; `dot` and `tree` require `--recurse`' -r -f -a "json\t'A single JSON array'
+ndjson\t'JSON Lines, one package per line'
+csv\t'Comma separated values, one package per row'
+tsv\t'Tab separated values, one package per row'
+dot\t'Graphviz DOT of the `--recurse` dependency graph'
+tree\t'A nested tree of the `--recurse` dependencies, like `pactree`'
+cyclonedx\t'CycloneDX software bill of materials, in JSON'
+spdx\t'SPDX 2.3 software bill of materials, in the tag-value format'
+spdx-json\t'SPDX 2.3 software bill of materials, in JSON'"
+complete -c pacjump -n "__fish_pacjump_needs_command" -l max-depth -d 'Truncate the `tree` output below the given depth' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l columns -d 'The columns of the `csv` and `tsv` formats, separated by commas, e.g. `name,version,depends_on.name`; by default all top-level fields except `sync_with`' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l list-separator -d 'The separator to join lists, e.g. `licenses`, within a single cell of the `csv` and `tsv` formats; a space by default' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l fields -d 'Output only the selected fields, separated by commas; nested fields are selected by their paths, e.g. `name,sync_with.version`' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l config -d 'Read an alternate pacman configuration file' -r -F
+complete -c pacjump -n "__fish_pacjump_needs_command" -l root -d 'Inspect an alternate installation root, e.g. a chroot or a mounted image; overrides `RootDir` in the configuration' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l dbpath -d 'Read the databases from an alternate location; overrides `DBPath` in the configuration' -r
+complete -c pacjump -n "__fish_pacjump_needs_command" -l from-dump -d 'Read the packages from a JSON dump of `pacjump`, instead of the pacman databases; the reverse dependencies are regenerated from the packages in the dump' -r -F
+complete -c pacjump -n "__fish_pacjump_needs_command" -l sync -d 'Query the sync databases; by default only the local database (of currently installed packages) is queried'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l all -d 'Query all packages, including those not explicitly installed; by default only explicitly installed packages are shown'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l plain -d 'Output package info from the current database only; by default we enrich the output by combining information from both the local and the sync databases'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l all-paths -d 'Show all the chains found by `--why`, instead of the shortest ones'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l foreign -d 'Query only the installed packages that are not found in the sync databases, e.g. those from the AUR or built locally; the same as `pacman -Qm`'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l native -d 'Query only the installed packages that are found in the sync databases; the same as `pacman -Qn`'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l orphans -d 'Report the orphans, i.e. the packages installed as dependencies that are no longer required, including those only wanted optionally and the cycles of packages that only require each other'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l upgradable -d 'List the installed packages that can be upgraded from the sync databases, as they are synced, with the old and new versions and the size changes; the same as `pacman -Qu`'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l cycles -d 'Report the dependency cycles, i.e. the groups of packages that depend on each other, with the dependency strings forming each cycle; the installed packages are inspected, or the sync packages with `--sync`'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l check -d 'Exit with an error if `--cycles` finds any cycle, e.g. to check a repository in CI'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l optional -d '`--recurse` installed optional dependencies as well; for `--reverse-recurse` and `--why`, follow the packages that optionally depend on the given package as well; for `--cycles`, follow the optional dependencies as well'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l build -d '`--recurse` the build dependencies, i.e. the `makedepends` and `checkdepends` of the given package, besides its runtime dependencies; the runtime dependencies are followed below. This is best used with `--sync`, as the local database may lack the build dependencies'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l summary -d '`--recurse` or `--reverse-recurse`, but only prints package names and versions; for `--why`, only prints the chains'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l ndjson -d 'Output JSON Lines (NDJSON), one package per line, as they are generated; by default a single JSON array is printed at the end. This is a shorthand for `--format=ndjson`'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l envelope -d 'Wrap the `json` output in an envelope, with the schema version and the information on the inspected system'
+complete -c pacjump -n "__fish_pacjump_needs_command" -l print-schema -d 'Print the JSON Schema of the `json` output and exit'
+complete -c pacjump -n "__fish_pacjump_needs_command" -s h -l help -d 'Print help (see more with \'--help\')'
+complete -c pacjump -n "__fish_pacjump_needs_command" -f -a "diff" -d 'Compare two JSON dumps of `pacjump`'
+complete -c pacjump -n "__fish_pacjump_needs_command" -f -a "help" -d 'Print this message or the help of the given subcommand(s)'
+complete -c pacjump -n "__fish_pacjump_using_subcommand diff" -l summary -d 'Print a human-readable summary, instead of the JSON report'
+complete -c pacjump -n "__fish_pacjump_using_subcommand diff" -s h -l help -d 'Print help'
+complete -c pacjump -n "__fish_pacjump_using_subcommand help; and not __fish_seen_subcommand_from diff help" -f -a "diff" -d 'Compare two JSON dumps of `pacjump`'
+complete -c pacjump -n "__fish_pacjump_using_subcommand help; and not __fish_seen_subcommand_from diff help" -f -a "help" -d 'Print this message or the help of the given subcommand(s)'
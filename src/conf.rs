@@ -0,0 +1,426 @@
+//! A module that parses `pacman.conf` natively into a typed [`PacmanConf`],
+//! so that `pacjump` works without the `pacman-conf` binary, e.g. in
+//! containers or on foreign hosts inspecting an Arch Linux root.
+//!
+//! Only the directives relevant to reading the databases are kept; other
+//! directives are accepted and ignored, just like `pacman` does for options
+//! that it does not recognize.
+//!
+//! This is a re-implementation of the following pacman functions:
+//!
+//! - `_parse_config`, `_parse_options`, `_parse_repo`, `process_include`:
+//!   <https://gitlab.archlinux.org/pacman/pacman/-/blob/master/src/pacman/conf.c>
+//! - `parse_ini`: <https://gitlab.archlinux.org/pacman/pacman/-/blob/master/src/pacman/ini.c>
+//!
+
+use alpm::{SigLevel, Usage};
+
+use crate::siglevel::process_siglevel;
+use std::path::{Path, PathBuf};
+
+/// The default location of the pacman configuration file.
+pub const DEFAULT_CONF_PATH: &str = "/etc/pacman.conf";
+
+/// The maximum depth of nested `Include` directives; same as pacman's.
+const MAX_INCLUDE_DEPTH: u32 = 10;
+
+/// The typed content of a `pacman.conf` file, after all `Include`
+/// directives are resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacmanConf {
+    /// `RootDir` from `[options]`; defaults to `/`.
+    pub root_dir: String,
+    /// `DBPath` from `[options]`; defaults to `var/lib/pacman/` under
+    /// the [`root_dir`][PacmanConf::root_dir].
+    pub db_path: String,
+    /// `Architecture` from `[options]`, with `auto` resolved to the
+    /// architecture of the running machine; as in pacman, this defaults to
+    /// `auto` if unset.
+    pub architectures: Vec<String>,
+    /// The default `SigLevel` tokens from `[options]`, as they are written.
+    pub sig_level: Vec<String>,
+    /// The repositories, in the order of their appearance.
+    pub repos: Vec<RepoConf>,
+}
+
+/// The configuration of a single repository section in `pacman.conf`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepoConf {
+    pub name: String,
+    /// `Server` urls, with `$repo` and `$arch` substituted.
+    pub servers: Vec<String>,
+    /// `Usage` of the repository; defaults to [`Usage::ALL`].
+    pub usage: Usage,
+    /// The `SigLevel` tokens of the repository, as they are written;
+    /// these are stacked onto the default [`PacmanConf::sig_level`].
+    pub sig_level: Vec<String>,
+}
+
+impl RepoConf {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            servers: Vec::new(),
+            usage: Usage::empty(),
+            sig_level: Vec::new(),
+        }
+    }
+}
+
+/// Overrides of the `pacman.conf` directives, e.g. from the command line,
+/// for inspecting chroots and container images. Just like pacman, the
+/// overrides take precedence over the directives in the file.
+#[derive(Debug, Clone, Default)]
+pub struct ConfOverrides {
+    pub root_dir: Option<String>,
+    pub db_path: Option<String>,
+}
+
+/// The section that a directive belongs to.
+enum Section {
+    None,
+    Options,
+    Repo(usize),
+}
+
+/// The intermediate state while parsing `pacman.conf` and its includes.
+struct Parser {
+    section: Section,
+    root_dir: Option<String>,
+    db_path: Option<String>,
+    architectures: Vec<String>,
+    sig_level: Vec<String>,
+    repos: Vec<RepoConf>,
+}
+
+impl PacmanConf {
+    /// Reads and parses a `pacman.conf` file from its `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::from_file_with_overrides(path, &ConfOverrides::default())
+    }
+
+    /// Reads and parses a `pacman.conf` file from its `path`, with some
+    /// directives replaced by the [`ConfOverrides`]. When only the root is
+    /// overridden, the database path is located under the new root, unless
+    /// `DBPath` is set explicitly in the file.
+    ///
+    /// A missing file at the [`DEFAULT_CONF_PATH`] is not an error; the
+    /// defaults are used instead, with no repositories.
+    pub fn from_file_with_overrides<P: AsRef<Path>>(
+        path: P,
+        overrides: &ConfOverrides,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let mut parser = Parser::new();
+        parser.root_dir = overrides.root_dir.clone();
+        parser.db_path = overrides.db_path.clone();
+        if path != Path::new(DEFAULT_CONF_PATH) || path.exists() {
+            parser.parse_file(path, 0)?;
+        }
+        Ok(parser.finish())
+    }
+
+    /// Parses the `content` of a `pacman.conf` file; the `path` is only
+    /// used to locate errors.
+    ///
+    /// ### Examples:
+    ///
+    /// ```
+    /// # use alpm::Usage;
+    /// # use pacjump::conf::PacmanConf;
+    /// #
+    /// let conf = PacmanConf::parse("
+    ///     [options]
+    ///     RootDir = /mnt/chroot/
+    ///     Architecture = x86_64 x86_64_v3
+    ///     SigLevel = Required DatabaseOptional
+    ///     Color  # boolean directives are ignored
+    ///
+    ///     [core]
+    ///     Server = https://mirror.example.org/$repo/os/$arch
+    ///
+    ///     [custom]
+    ///     Usage = Sync Search
+    ///     SigLevel = Optional TrustAll
+    /// ", "pacman.conf").unwrap();
+    ///
+    /// assert_eq!(conf.root_dir, "/mnt/chroot/");
+    /// assert_eq!(conf.db_path, "/mnt/chroot/var/lib/pacman/");
+    /// assert_eq!(conf.architectures, ["x86_64", "x86_64_v3"]);
+    /// assert_eq!(conf.sig_level, ["Required", "DatabaseOptional"]);
+    ///
+    /// let [core, custom] = &conf.repos[..] else { panic!() };
+    /// assert_eq!(core.servers, ["https://mirror.example.org/core/os/x86_64"]);
+    /// assert_eq!(core.usage, Usage::ALL);
+    /// assert_eq!(custom.usage, Usage::SYNC | Usage::SEARCH);
+    /// assert_eq!(custom.sig_level, ["Optional", "TrustAll"]);
+    /// ```
+    ///
+    /// Without `Architecture`, the architecture of the running machine is
+    /// assumed, as with `auto`:
+    ///
+    /// ```
+    /// # use pacjump::conf::PacmanConf;
+    /// let conf = PacmanConf::parse("[core]\nServer = https://x/$arch", "pacman.conf")?;
+    /// let uname = std::process::Command::new("uname").arg("-m").output()?;
+    /// let machine = String::from_utf8(uname.stdout)?.trim().to_string();
+    /// assert_eq!(conf.architectures, [machine.as_str()]);
+    /// assert_eq!(conf.repos[0].servers, [format!("https://x/{machine}")]);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    /// Errors are reported with the file name and the line number:
+    ///
+    /// ```
+    /// # use pacjump::conf::PacmanConf;
+    /// let err = PacmanConf::parse("\n\nServer = foo", "pacman.conf").unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "pacman.conf:3: all directives must belong to a section"
+    /// );
+    ///
+    /// let err = PacmanConf::parse("[core]\nUsage = Never", "pacman.conf").unwrap_err();
+    /// assert_eq!(err.to_string(), "pacman.conf:2: unknown Usage: \"Never\"");
+    ///
+    /// let err = PacmanConf::parse("[options]\nSigLevel = Always", "pacman.conf").unwrap_err();
+    /// assert_eq!(
+    ///     err.to_string(),
+    ///     "pacman.conf:2: invalid value for 'SigLevel': \"Always\""
+    /// );
+    /// ```
+    ///
+    pub fn parse<P: AsRef<Path>>(content: &str, path: P) -> anyhow::Result<Self> {
+        let mut parser = Parser::new();
+        parser.parse_content(content, path.as_ref(), 0)?;
+        Ok(parser.finish())
+    }
+
+    /// Returns the names of all repositories, like `pacman-conf --repo-list`.
+    pub fn repo_list(&self) -> impl Iterator<Item = &str> {
+        self.repos.iter().map(|repo| repo.name.as_str())
+    }
+}
+
+impl Parser {
+    fn new() -> Self {
+        Self {
+            section: Section::None,
+            root_dir: None,
+            db_path: None,
+            architectures: Vec::new(),
+            sig_level: Vec::new(),
+            repos: Vec::new(),
+        }
+    }
+
+    fn parse_file(&mut self, path: &Path, depth: u32) -> anyhow::Result<()> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+        self.parse_content(&content, path, depth)
+    }
+
+    fn parse_content(&mut self, content: &str, path: &Path, depth: u32) -> anyhow::Result<()> {
+        for (index, line) in content.lines().enumerate() {
+            self.parse_line(line, path, depth)
+                .map_err(|err| anyhow::anyhow!("{}:{}: {err}", path.display(), index + 1))?;
+        }
+        Ok(())
+    }
+
+    /// Parses a single line of `pacman.conf`; the error is located by the
+    /// caller.
+    fn parse_line(&mut self, line: &str, path: &Path, depth: u32) -> anyhow::Result<()> {
+        let line = match line.split_once('#') {
+            Some((content, _comment)) => content,
+            None => line,
+        }
+        .trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(name) = line.strip_prefix('[') {
+            let Some(name) = name.strip_suffix(']') else {
+                anyhow::bail!("unterminated section header: {:?}", line)
+            };
+            return self.enter_section(name.trim());
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), Some(value.trim())),
+            None => (line, None),
+        };
+        if let Section::None = self.section {
+            anyhow::bail!("all directives must belong to a section");
+        }
+        let Some(value) = value else {
+            // boolean directives, e.g. `Color`, are irrelevant to us
+            return Ok(());
+        };
+
+        match (key, &self.section) {
+            ("Include", _) => self.include(value, path, depth),
+            (_, Section::Options) => self.parse_option(key, value),
+            (_, Section::Repo(index)) => {
+                let index = *index;
+                parse_repo_directive(&mut self.repos[index], key, value)
+            }
+            (_, Section::None) => unreachable!(),
+        }
+    }
+
+    fn enter_section(&mut self, name: &str) -> anyhow::Result<()> {
+        self.section = match name {
+            "" => anyhow::bail!("empty section name"),
+            "options" => Section::Options,
+            "local" => anyhow::bail!("repository with name 'local' is reserved"),
+            name => match self.repos.iter().position(|repo| repo.name == name) {
+                Some(index) => Section::Repo(index),
+                None => {
+                    self.repos.push(RepoConf::new(name));
+                    Section::Repo(self.repos.len() - 1)
+                }
+            },
+        };
+        Ok(())
+    }
+
+    fn parse_option(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        match key {
+            // just like pacman, the first occurrence wins, so that
+            // the paths specified as overrides are not overwritten
+            "RootDir" if self.root_dir.is_none() => self.root_dir = Some(value.to_string()),
+            "DBPath" if self.db_path.is_none() => self.db_path = Some(value.to_string()),
+            "Architecture" => {
+                for arch in value.split_whitespace() {
+                    let arch = match arch {
+                        "auto" => machine_arch(),
+                        arch => arch.to_string(),
+                    };
+                    self.architectures.push(arch);
+                }
+            }
+            "SigLevel" => extend_siglevels(&mut self.sig_level, value)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Parses all files matching the `Include` glob, in the current section.
+    fn include(&mut self, pattern: &str, path: &Path, depth: u32) -> anyhow::Result<()> {
+        if depth + 1 >= MAX_INCLUDE_DEPTH {
+            anyhow::bail!("exceeded maximum include depth of {}", MAX_INCLUDE_DEPTH);
+        }
+        let files = glob::glob(pattern)?.collect::<Result<Vec<PathBuf>, _>>()?;
+        if files.is_empty() {
+            eprintln!(
+                "{}: no files match the include glob: {:?}",
+                path.display(),
+                pattern
+            );
+        }
+        for file in files {
+            self.parse_file(&file, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Fills in the defaults and substitutes the server urls, just like
+    /// pacman does after the configuration is parsed.
+    fn finish(self) -> PacmanConf {
+        let root_dir = self.root_dir.clone().unwrap_or("/".into());
+        let db_path = match (&self.db_path, &self.root_dir) {
+            (Some(db_path), _) => db_path.clone(),
+            (None, Some(root_dir)) => {
+                format!("{}/var/lib/pacman/", root_dir.trim_end_matches('/'))
+            }
+            (None, None) => "/var/lib/pacman/".into(),
+        };
+        // pacman treats a missing `Architecture` as `auto`
+        let architectures = match self.architectures.is_empty() {
+            true => vec![machine_arch()],
+            false => self.architectures,
+        };
+        let arch = &architectures[0];
+        let repos = self
+            .repos
+            .into_iter()
+            .map(|repo| RepoConf {
+                servers: repo
+                    .servers
+                    .iter()
+                    .map(|server| server.replace("$repo", &repo.name).replace("$arch", arch))
+                    .collect(),
+                usage: match repo.usage.is_empty() {
+                    true => Usage::ALL,
+                    false => repo.usage,
+                },
+                ..repo
+            })
+            .collect();
+        PacmanConf {
+            root_dir,
+            db_path,
+            architectures,
+            sig_level: self.sig_level,
+            repos,
+        }
+    }
+}
+
+fn parse_repo_directive(repo: &mut RepoConf, key: &str, value: &str) -> anyhow::Result<()> {
+    match key {
+        "Server" => repo.servers.push(value.to_string()),
+        "SigLevel" => extend_siglevels(&mut repo.sig_level, value)?,
+        "Usage" => {
+            for usage in value.split_whitespace() {
+                repo.usage |= match usage {
+                    "Sync" => Usage::SYNC,
+                    "Search" => Usage::SEARCH,
+                    "Install" => Usage::INSTALL,
+                    "Upgrade" => Usage::UPGRADE,
+                    "All" => Usage::ALL,
+                    x => anyhow::bail!("unknown Usage: {:?}", x),
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Validates and appends the whitespace separated `SigLevel` tokens in
+/// `value` to a list.
+fn extend_siglevels(tokens: &mut Vec<String>, value: &str) -> anyhow::Result<()> {
+    for token in value.split_whitespace() {
+        process_siglevel(SigLevel::USE_DEFAULT, token)?;
+        tokens.push(token.to_string());
+    }
+    Ok(())
+}
+
+/// The `utsname` of Linux, as it is filled in by `uname(2)`: the `sysname`,
+/// `nodename`, `release`, `version`, `machine` and `domainname` fields.
+#[repr(C)]
+struct UtsName([[std::ffi::c_char; 65]; 6]);
+
+extern "C" {
+    fn uname(buf: *mut UtsName) -> std::ffi::c_int;
+}
+
+/// Returns the hardware name of the running machine, i.e. `uname -m`, which
+/// pacman uses for `Architecture = auto`; falls back to the architecture
+/// that `pacjump` is built for, if `uname` fails.
+fn machine_arch() -> String {
+    let mut utsname = UtsName([[0; 65]; 6]);
+    // SAFETY: `uname` only writes the NUL-terminated fields of `utsname`
+    if unsafe { uname(&mut utsname) } != 0 {
+        return std::env::consts::ARCH.to_string();
+    }
+    let machine = utsname.0[4].map(|x| x as u8);
+    let len = machine
+        .iter()
+        .position(|&x| x == 0)
+        .unwrap_or(machine.len());
+    String::from_utf8_lossy(&machine[..len]).into_owned()
+}
@@ -0,0 +1,178 @@
+//! A module that finds the dependency cycles among the packages, i.e. the
+//! groups of packages that depend on each other, as the strongly connected
+//! components of the [`DependencyGraph`]. The cycles are found among the
+//! installed packages, or the sync packages with `--sync`; the hard
+//! dependencies are followed, as well as the optional ones with
+//! `--optional`.
+
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use crate::graph::{DependencyGraph, Edge, EdgeKind};
+use crate::source::PackageSource;
+use crate::PackageFilters;
+
+/// A dependency within a [`Cycle`].
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CycleEdge {
+    pub from: String,
+    pub to: String,
+    pub dep_string: String,
+}
+
+/// A group of packages that depend on each other.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Cycle {
+    /// The packages of the cycle, sorted by names.
+    pub packages: Vec<String>,
+    /// All the dependencies among the packages of the cycle.
+    pub edges: Vec<CycleEdge>,
+    /// One of the shortest loops through the first package, as a sequence
+    /// of dependencies leading back to it.
+    pub shortest: Vec<CycleEdge>,
+}
+
+impl fmt::Display for Cycle {
+    /// Formats the [`shortest`][Cycle::shortest] loop of the cycle as e.g.
+    /// `foo -> bar (bar>=2) -> foo`, with the dependency string shown when
+    /// it differs from the package name.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(first) = self.shortest.first() else {
+            return Ok(());
+        };
+        write!(f, "{}", first.from)?;
+        for edge in &self.shortest {
+            write!(f, " -> {}", edge.to)?;
+            if edge.dep_string != edge.to {
+                write!(f, " ({})", edge.dep_string)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The dependency cycles among the packages, sorted by their packages.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::cycles::CycleReport;
+/// # use pacjump::source::DumpSource;
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "systemd", "version": "256-1",
+///       "depends_on": [{ "dep_string": "systemd-libs", "name": "systemd-libs", "depmod": "Any" }] },
+///     { "name": "systemd-libs", "version": "256-1",
+///       "depends_on": [{ "dep_string": "libudev.so", "name": "libudev.so", "depmod": "Any" }] },
+///     { "name": "udev-compat", "version": "1.0-1",
+///       "depends_on": [{ "dep_string": "systemd", "name": "systemd", "depmod": "Any" }],
+///       "provides": [{ "dep_string": "libudev.so", "name": "libudev.so", "depmod": "Any" }] },
+///     { "name": "python", "version": "3.12-1",
+///       "optional_deps": [{ "dep_string": "python-pip", "name": "python-pip", "depmod": "Any" }] },
+///     { "name": "python-pip", "version": "24.0-1",
+///       "depends_on": [{ "dep_string": "python", "name": "python", "depmod": "Any" }] }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+///
+/// let report = CycleReport::new(&source, &PackageFilters::parse_from(["pacjump", "--cycles"]));
+/// assert_eq!(report.cycles.len(), 1);
+/// assert_eq!(report.cycles[0].packages, ["systemd", "systemd-libs", "udev-compat"]);
+/// assert_eq!(report.cycles[0].edges.len(), 3);
+/// assert_eq!(
+///     report.cycles[0].to_string(),
+///     "systemd -> systemd-libs -> udev-compat (libudev.so) -> systemd"
+/// );
+///
+/// // the optional dependencies are followed with `--optional`:
+/// let filters = PackageFilters::parse_from(["pacjump", "--cycles", "--optional"]);
+/// let report = CycleReport::new(&source, &filters);
+/// assert_eq!(report.cycles[0].to_string(), "python -> python-pip -> python");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct CycleReport {
+    pub cycles: Vec<Cycle>,
+}
+
+impl CycleReport {
+    /// Finds the cycles among all the packages of the source; the filters
+    /// are expected to select all the packages, as `--cycles` does.
+    pub fn new<S>(source: &S, pkg_filters: &PackageFilters) -> Self
+    where
+        S: PackageSource + ?Sized,
+    {
+        let graph = DependencyGraph::new(source, pkg_filters);
+        let kinds: &[EdgeKind] = match pkg_filters.optional {
+            true => &[EdgeKind::Depends, EdgeKind::OptDepends],
+            false => &[EdgeKind::Depends],
+        };
+        let name = |idx: usize| graph.packages()[idx].name.to_string();
+
+        let mut report = Self::default();
+        for component in graph.strongly_connected(kinds) {
+            let members: BTreeSet<usize> = component.into_iter().collect();
+            // the dependencies within the component, by their origins
+            let mut internal: HashMap<usize, Vec<_>> = HashMap::new();
+            for &idx in &members {
+                let edges = graph.dependencies(idx, kinds);
+                let edges = edges.filter(|edge| members.contains(&edge.to));
+                internal.entry(idx).or_default().extend(edges);
+            }
+            let is_cycle = members.len() > 1 || internal.values().any(|x| !x.is_empty());
+            if !is_cycle {
+                continue;
+            }
+            let cycle_edge = |edge: &Edge| CycleEdge {
+                from: name(edge.from),
+                to: name(edge.to),
+                dep_string: edge.dep_string.clone(),
+            };
+
+            let mut packages: Vec<(String, usize)> =
+                members.iter().map(|&idx| (name(idx), idx)).collect();
+            packages.sort();
+            let mut edges: Vec<CycleEdge> =
+                internal.values().flatten().map(|x| cycle_edge(x)).collect();
+            edges.sort_by(|a, b| (&a.from, &a.to).cmp(&(&b.from, &b.to)));
+
+            // breadth first, from the first package back to itself
+            let start = packages[0].1;
+            let mut via = HashMap::new();
+            let mut queue = VecDeque::from([start]);
+            'search: while let Some(idx) = queue.pop_front() {
+                for &edge in &internal[&idx] {
+                    if edge.to == start {
+                        via.insert(start, edge);
+                        break 'search;
+                    }
+                    if let Entry::Vacant(entry) = via.entry(edge.to) {
+                        entry.insert(edge);
+                        queue.push_back(edge.to);
+                    }
+                }
+            }
+            let mut shortest = Vec::new();
+            let mut current = start;
+            while let Some(edge) = via.get(&current) {
+                shortest.push(cycle_edge(edge));
+                current = edge.from;
+                if current == start {
+                    break;
+                }
+            }
+            shortest.reverse();
+
+            report.cycles.push(Cycle {
+                packages: packages.into_iter().map(|(name, _)| name).collect(),
+                edges,
+                shortest,
+            });
+        }
+        report.cycles.sort_by(|a, b| a.packages.cmp(&b.packages));
+        report
+    }
+}
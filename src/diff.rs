@@ -0,0 +1,209 @@
+//! A module that compares two dumps of `pacjump`, e.g. taken on different
+//! days, and reports the packages which are added, removed, upgraded,
+//! downgraded, or whose install reason or repository has changed. The
+//! packages are matched by their names, and the versions are compared with
+//! pacman's [`vercmp`].
+
+use alpm::vercmp;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::info::OwnedPackageInfo;
+
+/// Compare two JSON dumps of `pacjump`
+#[derive(Debug, clap::Args)]
+pub struct DiffArgs {
+    /// The older dump
+    pub old: PathBuf,
+
+    /// The newer dump
+    pub new: PathBuf,
+
+    /// Print a human-readable summary, instead of the JSON report
+    #[arg(long)]
+    pub summary: bool,
+}
+
+/// A package that is present in only one of the dumps.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PackageEntry {
+    pub name: String,
+    pub version: String,
+    pub repository: Option<String>,
+}
+
+/// A change of some property of a package between the dumps.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Change<T> {
+    pub name: String,
+    pub old: T,
+    pub new: T,
+}
+
+/// The changes between two dumps, with the packages sorted by names.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::diff::DiffReport;
+/// # use pacjump::info::OwnedPackageInfo;
+/// let old: Vec<OwnedPackageInfo> = serde_json::from_str(r#"[
+///     { "name": "foo", "version": "1.0-1", "install_reason": "Depend" },
+///     { "name": "bar", "version": "2.0-1" },
+///     { "name": "baz", "version": "1:0.9-1" }
+/// ]"#)?;
+/// let new: Vec<OwnedPackageInfo> = serde_json::from_str(r#"[
+///     { "name": "foo", "version": "1.0.1-1", "install_reason": "Explicit" },
+///     { "name": "baz", "version": "1.0-1" },
+///     { "name": "qux", "version": "3.0-1" }
+/// ]"#)?;
+/// let report = DiffReport::new(&old, &new);
+/// assert_eq!(report.added[0].name, "qux");
+/// assert_eq!(report.removed[0].name, "bar");
+/// assert_eq!(report.upgraded[0].name, "foo");
+/// // the epoch takes precedence:
+/// assert_eq!(report.downgraded[0].name, "baz");
+/// assert_eq!(report.reason_changed[0].new, "Explicit");
+///
+/// assert_eq!(report.to_string().lines().next(), Some("added qux (3.0-1)"));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct DiffReport {
+    pub added: Vec<PackageEntry>,
+    pub removed: Vec<PackageEntry>,
+    pub upgraded: Vec<Change<String>>,
+    pub downgraded: Vec<Change<String>>,
+    pub reason_changed: Vec<Change<String>>,
+    /// The repositories which the packages come from; a local package is
+    /// attributed to the repository of its sync counterpart, or to none if
+    /// it is foreign.
+    pub repository_changed: Vec<Change<Option<String>>>,
+}
+
+/// Returns the repository which a package comes from; see
+/// [`DiffReport::repository_changed`].
+fn origin(pkg: &OwnedPackageInfo) -> Option<&str> {
+    match pkg.repository.as_deref() {
+        Some("local") | None => pkg.sync_with.as_ref()?.repository.as_deref(),
+        repository => repository,
+    }
+}
+
+/// Indexes the packages by their names, keeping the first one of each name.
+fn by_name(packages: &[OwnedPackageInfo]) -> BTreeMap<&str, &OwnedPackageInfo> {
+    let mut map = BTreeMap::new();
+    for pkg in packages.iter().rev() {
+        map.insert(pkg.name.as_str(), pkg);
+    }
+    map
+}
+
+impl PackageEntry {
+    fn new(pkg: &OwnedPackageInfo) -> Self {
+        Self {
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            repository: origin(pkg).map(String::from),
+        }
+    }
+}
+
+impl DiffReport {
+    /// Compares the packages of two dumps; if a name appears more than once
+    /// in a dump, e.g. in several sync repositories, the first one is used.
+    pub fn new(old: &[OwnedPackageInfo], new: &[OwnedPackageInfo]) -> Self {
+        let (old, new) = (by_name(old), by_name(new));
+        let mut report = Self::default();
+
+        for (name, old_pkg) in &old {
+            let Some(new_pkg) = new.get(name) else {
+                report.removed.push(PackageEntry::new(old_pkg));
+                continue;
+            };
+            let change = |old: &str, new: &str| Change {
+                name: name.to_string(),
+                old: old.to_string(),
+                new: new.to_string(),
+            };
+            match vercmp(old_pkg.version.as_str(), new_pkg.version.as_str()) {
+                Ordering::Less => report
+                    .upgraded
+                    .push(change(&old_pkg.version, &new_pkg.version)),
+                Ordering::Greater => report
+                    .downgraded
+                    .push(change(&old_pkg.version, &new_pkg.version)),
+                Ordering::Equal => {}
+            }
+            if old_pkg.install_reason != new_pkg.install_reason {
+                report
+                    .reason_changed
+                    .push(change(&old_pkg.install_reason, &new_pkg.install_reason));
+            }
+            if origin(old_pkg) != origin(new_pkg) {
+                report.repository_changed.push(Change {
+                    name: name.to_string(),
+                    old: origin(old_pkg).map(String::from),
+                    new: origin(new_pkg).map(String::from),
+                });
+            }
+        }
+        report.added = new
+            .iter()
+            .filter(|(name, _)| !old.contains_key(*name))
+            .map(|(_, pkg)| PackageEntry::new(pkg))
+            .collect();
+        report
+    }
+
+    /// Returns whether there is no change at all.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl fmt::Display for DiffReport {
+    /// Formats the report as a human-readable summary, one change per line,
+    /// in the style of `pacman.log`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let repo = |repository: &Option<String>| repository.clone().unwrap_or("none".into());
+        for pkg in &self.added {
+            writeln!(f, "added {} ({})", pkg.name, pkg.version)?;
+        }
+        for pkg in &self.removed {
+            writeln!(f, "removed {} ({})", pkg.name, pkg.version)?;
+        }
+        for (action, changes) in [
+            ("upgraded", &self.upgraded),
+            ("downgraded", &self.downgraded),
+            ("reason changed", &self.reason_changed),
+        ] {
+            for change in changes {
+                writeln!(
+                    f,
+                    "{action} {} ({} -> {})",
+                    change.name, change.old, change.new
+                )?;
+            }
+        }
+        for change in &self.repository_changed {
+            let (old, new) = (repo(&change.old), repo(&change.new));
+            writeln!(f, "repository changed {} ({old} -> {new})", change.name)?;
+        }
+        write!(
+            f,
+            "# {} added, {} removed, {} upgraded, {} downgraded, \
+            {} reason changed, {} repository changed",
+            self.added.len(),
+            self.removed.len(),
+            self.upgraded.len(),
+            self.downgraded.len(),
+            self.reason_changed.len(),
+            self.repository_changed.len(),
+        )
+    }
+}
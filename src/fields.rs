@@ -0,0 +1,336 @@
+//! A module that projects the serialized package information onto a
+//! selection of fields, e.g. `name,version,sync_with.version`.
+//!
+//! The projection is applied during serialization, by wrapping the [`serde`]
+//! serializer: fields of structs, e.g. [`PackageInfo`] and [`DepInfo`], are
+//! skipped unless they are selected, so that the unwanted fields are never
+//! serialized at all. A nested path applies to the elements of lists, e.g.
+//! `depends_on.name` selects the `name` of every dependency.
+//!
+//! [`PackageInfo`]: crate::info::PackageInfo
+//! [`DepInfo`]: crate::info::DepInfo
+
+use serde::ser::{self, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// A tree of selected fields; the default selects everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldSelection(Option<BTreeMap<String, FieldSelection>>);
+
+impl FieldSelection {
+    /// Selects all fields.
+    pub const ALL: Self = Self(None);
+
+    /// Returns whether all fields are selected.
+    pub fn is_all(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Returns the sub-selection of a field, or [`None`] if the field is not
+    /// selected.
+    pub fn get(&self, field: &str) -> Option<&Self> {
+        match &self.0 {
+            None => Some(&Self::ALL),
+            Some(fields) => fields.get(field),
+        }
+    }
+
+    /// Returns the names of the selected top-level fields; this is empty if
+    /// all fields are selected.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .flat_map(|fields| fields.keys().map(String::as_str))
+    }
+
+    /// Adds a field path, e.g. `sync_with.version`, to the selection.
+    fn insert<'a, I: Iterator<Item = &'a str>>(&mut self, mut path: I) {
+        let Some(field) = path.next() else {
+            // the full subtree is selected
+            self.0 = None;
+            return;
+        };
+        if let Some(fields) = &mut self.0 {
+            fields
+                .entry(field.to_string())
+                .or_insert_with(|| Self(Some(BTreeMap::new())))
+                .insert(path);
+        }
+    }
+}
+
+impl FromStr for FieldSelection {
+    type Err = anyhow::Error;
+
+    /// Parses a comma separated list of field paths.
+    ///
+    /// ### Examples:
+    ///
+    /// ```
+    /// # use pacjump::fields::FieldSelection;
+    /// let fields: FieldSelection = "name, sync_with.version, sync_with".parse()?;
+    /// assert!(fields.get("name").unwrap().is_all());
+    /// assert!(fields.get("sync_with").unwrap().is_all());
+    /// assert_eq!(fields.get("version"), None);
+    /// assert_eq!(fields.fields().collect::<Vec<_>>(), ["name", "sync_with"]);
+    ///
+    /// let fields: FieldSelection = "depends_on.name".parse()?;
+    /// let depends_on = fields.get("depends_on").unwrap();
+    /// assert!(depends_on.get("name").is_some());
+    /// assert!(depends_on.get("version").is_none());
+    ///
+    /// assert!("name,,version".parse::<FieldSelection>().is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    ///
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut selection = Self(Some(BTreeMap::new()));
+        for path in s.split(',').map(str::trim) {
+            if path.split('.').any(str::is_empty) {
+                anyhow::bail!("invalid field path: {:?}", path);
+            }
+            selection.insert(path.split('.'));
+        }
+        Ok(selection)
+    }
+}
+
+/// A wrapper of a [`Serialize`] value, which serializes only the selected
+/// fields of the value.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::fields::{FieldSelection, Projection};
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// struct Dep { name: &'static str, version: &'static str }
+///
+/// #[derive(Serialize)]
+/// struct Pkg { name: &'static str, size: i64, depends_on: Vec<Dep> }
+///
+/// let pkg = Pkg {
+///     name: "foo",
+///     size: 42,
+///     depends_on: vec![Dep { name: "bar", version: "1.0" }],
+/// };
+/// let fields = "name,depends_on.name".parse::<FieldSelection>()?;
+/// let json = serde_json::to_string(&Projection::new(&pkg, &fields))?;
+/// assert_eq!(json, r#"{"name":"foo","depends_on":[{"name":"bar"}]}"#);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct Projection<'f, T> {
+    value: T,
+    fields: &'f FieldSelection,
+}
+
+impl<'f, T: Serialize> Projection<'f, T> {
+    pub fn new(value: T, fields: &'f FieldSelection) -> Self {
+        Self { value, fields }
+    }
+}
+
+impl<T: Serialize> Serialize for Projection<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.fields.is_all() {
+            true => self.value.serialize(serializer),
+            false => self.value.serialize(Projector {
+                inner: serializer,
+                fields: self.fields,
+            }),
+        }
+    }
+}
+
+/// A [`Serializer`] adapter that skips the fields which are not selected.
+struct Projector<'f, S> {
+    inner: S,
+    fields: &'f FieldSelection,
+}
+
+/// Forwards the serialization of a primitive value to the inner serializer.
+macro_rules! forward {
+    ($($method:ident($($ty:ty)?)),* $(,)?) => {
+        $(
+            fn $method(self $(, v: $ty)?) -> Result<S::Ok, S::Error> {
+                self.inner.$method($(v as $ty)?)
+            }
+        )*
+    };
+}
+
+impl<'f, S: Serializer> Serializer for Projector<'f, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = ProjectSeq<'f, S::SerializeSeq>;
+    type SerializeTuple = S::SerializeTuple;
+    type SerializeTupleStruct = S::SerializeTupleStruct;
+    type SerializeTupleVariant = S::SerializeTupleVariant;
+    type SerializeMap = S::SerializeMap;
+    type SerializeStruct = ProjectStruct<'f, S::SerializeStruct>;
+    type SerializeStructVariant = S::SerializeStructVariant;
+
+    forward!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<S::Ok, S::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_variant(name, index, variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        // transparent, e.g. for `PacList`
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_newtype_variant(name, index, variant, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        Ok(ProjectSeq {
+            inner: self.inner.serialize_seq(len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        self.inner.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        self.inner.serialize_tuple_struct(name, len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        self.inner
+            .serialize_tuple_variant(name, index, variant, len)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        self.inner.serialize_map(len)
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        Ok(ProjectStruct {
+            inner: self.inner.serialize_struct(name, len)?,
+            fields: self.fields,
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        self.inner
+            .serialize_struct_variant(name, index, variant, len)
+    }
+}
+
+/// Projects every element of a list onto the same selection.
+struct ProjectSeq<'f, S> {
+    inner: S,
+    fields: &'f FieldSelection,
+}
+
+impl<S: ser::SerializeSeq> ser::SerializeSeq for ProjectSeq<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), S::Error> {
+        self.inner
+            .serialize_element(&Projection::new(value, self.fields))
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
+
+/// Skips the fields of a struct which are not selected, and projects the
+/// selected ones onto their sub-selections.
+struct ProjectStruct<'f, S> {
+    inner: S,
+    fields: &'f FieldSelection,
+}
+
+impl<S: ser::SerializeStruct> ser::SerializeStruct for ProjectStruct<'_, S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), S::Error> {
+        match self.fields.get(key) {
+            Some(fields) => self
+                .inner
+                .serialize_field(key, &Projection::new(value, fields)),
+            None => self.inner.skip_field(key),
+        }
+    }
+
+    fn end(self) -> Result<S::Ok, S::Error> {
+        self.inner.end()
+    }
+}
@@ -0,0 +1,423 @@
+//! A module that builds the dependency graph of the packages from a
+//! [`PackageSource`] once, with the dependencies resolved to their
+//! satisfiers, so that the analyses, e.g. [`orphans`][crate::orphans] and
+//! [`why`][crate::why], share the resolution instead of walking the
+//! databases again.
+//!
+//! The nodes of the graph are the packages, referred to by their indices,
+//! and the edges are labelled by an [`EdgeKind`].
+
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::info::{DepInfo, PacList, PackageInfo};
+use crate::source::PackageSource;
+use crate::PackageFilters;
+
+/// The kinds of the edges of a [`DependencyGraph`].
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum EdgeKind {
+    /// A dependency in [`depends_on`][PackageInfo::depends_on].
+    Depends,
+    /// A dependency in [`optional_deps`][PackageInfo::optional_deps].
+    OptDepends,
+    /// A dependency in [`makedepends`][PackageInfo::makedepends].
+    MakeDepends,
+    /// A dependency in [`checkdepends`][PackageInfo::checkdepends].
+    CheckDepends,
+    /// A provision in [`provides`][PackageInfo::provides] of the name of
+    /// another package, e.g. `pipewire-jack` provides `jack`.
+    Provides,
+}
+
+impl EdgeKind {
+    /// All the kinds of the edges.
+    pub const ALL: &'static [Self] = &[
+        Self::Depends,
+        Self::OptDepends,
+        Self::MakeDepends,
+        Self::CheckDepends,
+        Self::Provides,
+    ];
+}
+
+/// An edge of a [`DependencyGraph`], from a package to its dependency, or
+/// to the package whose name it provides.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+    /// The dependency string, or the provision, e.g. `sh` for `bash`.
+    pub dep_string: String,
+}
+
+/// The direction in which a [`DependencyGraph`] is traversed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    /// Along the edges, i.e. from the packages to their dependencies.
+    Dependencies,
+    /// Against the edges, i.e. from the packages to their dependents.
+    Dependents,
+}
+
+/// The dependency graph of the packages of a [`PackageSource`].
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::graph::{DependencyGraph, Direction, EdgeKind};
+/// # use pacjump::source::DumpSource;
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "app", "version": "1.0-1",
+///       "depends_on": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }],
+///       "makedepends": [{ "dep_string": "make", "name": "make", "depmod": "Any" }] },
+///     { "name": "bash", "version": "5.2-1",
+///       "depends_on": [{ "dep_string": "glibc>=2.38", "name": "glibc", "depmod": "Ge", "version": "2.38" }],
+///       "provides": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] },
+///     { "name": "glibc", "version": "2.37-1" },
+///     { "name": "glibc-git", "version": "1.0-1",
+///       "provides": [{ "dep_string": "glibc=2.40", "name": "glibc", "depmod": "Eq", "version": "2.40" }] },
+///     { "name": "make", "version": "4.4-1",
+///       "depends_on": [{ "dep_string": "glibc", "name": "glibc", "depmod": "Any" }] }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+/// let filters = PackageFilters::parse_from(["pacjump", "--all"]);
+///
+/// let graph = DependencyGraph::new(&source, &filters);
+/// let [app, bash, glibc, git] = ["app", "bash", "glibc", "glibc-git"].map(|x| graph.index_of(x).unwrap());
+/// assert_eq!(graph.dependencies(app, EdgeKind::ALL).count(), 2);
+/// assert_eq!(graph.dependents(bash, &[EdgeKind::Depends]).next().unwrap().dep_string, "sh");
+/// // `glibc` is too old for `bash`, which is satisfied by the provision:
+/// assert_eq!(graph.dependencies(bash, EdgeKind::ALL).next().unwrap().to, git);
+///
+/// let runtime = &[EdgeKind::Depends];
+/// assert!(graph.is_reachable(app, git, runtime));
+/// assert_eq!(graph.bfs(app, runtime, Direction::Dependencies), [app, bash, git]);
+/// assert_eq!(graph.bfs(glibc, EdgeKind::ALL, Direction::Dependents).len(), 5);
+///
+/// let order = graph.topological_order(EdgeKind::ALL);
+/// let position = |node| order.iter().position(|&x| x == node).unwrap();
+/// assert!(position(git) < position(bash) && position(bash) < position(app));
+///
+/// let closure = graph.subgraph(graph.bfs(app, runtime, Direction::Dependencies));
+/// assert_eq!(closure.len(), 3);
+/// assert_eq!(closure.edges().len(), 2);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct DependencyGraph<'a> {
+    packages: Vec<PackageInfo<'a>>,
+    index: HashMap<&'a str, usize>,
+    edges: Vec<Edge>,
+    /// the indices of the edges from and to each package
+    outgoing: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+}
+
+impl<'a> DependencyGraph<'a> {
+    /// Builds the graph of the packages selected by the filters; see
+    /// [`DependencyGraph::from_packages`].
+    pub fn new<S>(source: &'a S, pkg_filters: &'a PackageFilters) -> Self
+    where
+        S: PackageSource + ?Sized,
+    {
+        Self::from_packages(source, pkg_filters, source.packages(pkg_filters).collect())
+    }
+
+    /// Builds the graph of the given packages, e.g. a closure, with the
+    /// dependencies resolved by [`PackageSource::find_satisfier`]; the
+    /// dependencies satisfied by the packages outside of the given ones are
+    /// left out. Each distinct dependency string is only resolved once.
+    pub fn from_packages<S>(
+        source: &S,
+        pkg_filters: &PackageFilters,
+        packages: Vec<PackageInfo<'a>>,
+    ) -> Self
+    where
+        S: PackageSource + ?Sized,
+    {
+        let index = index_by_name(&packages);
+        let mut ids = HashMap::new();
+        for (idx, pkg) in packages.iter().enumerate() {
+            ids.entry((pkg.name, pkg.version)).or_insert(idx);
+        }
+        let mut resolved: HashMap<String, Option<usize>> = HashMap::new();
+        let mut satisfier = |dep: &DepInfo| {
+            if let Some(&to) = resolved.get(&dep.dep_string) {
+                return to;
+            }
+            let to = source
+                .find_satisfier(pkg_filters, dep)
+                .and_then(|satisfier| {
+                    let id = (satisfier.name, satisfier.version);
+                    ids.get(&id).or_else(|| index.get(satisfier.name)).copied()
+                });
+            resolved.insert(dep.dep_string.clone(), to);
+            to
+        };
+
+        let mut edges = Vec::new();
+        let mut resolve = |from: usize, kind, deps: &PacList<DepInfo>| {
+            for dep in deps {
+                if let Some(to) = satisfier(dep) {
+                    edges.push(Edge {
+                        from,
+                        to,
+                        kind,
+                        dep_string: dep.dep_string.clone(),
+                    });
+                }
+            }
+        };
+        for (idx, pkg) in packages.iter().enumerate() {
+            resolve(idx, EdgeKind::Depends, &pkg.depends_on);
+            resolve(idx, EdgeKind::OptDepends, &pkg.optional_deps);
+            resolve(idx, EdgeKind::MakeDepends, &pkg.makedepends);
+            resolve(idx, EdgeKind::CheckDepends, &pkg.checkdepends);
+        }
+        for (idx, pkg) in packages.iter().enumerate() {
+            for provision in pkg.provides.iter() {
+                match index.get(provision.name) {
+                    Some(&to) if to != idx => edges.push(Edge {
+                        from: idx,
+                        to,
+                        kind: EdgeKind::Provides,
+                        dep_string: provision.dep_string.clone(),
+                    }),
+                    _ => continue,
+                }
+            }
+        }
+        Self::from_parts(packages, edges)
+    }
+
+    fn from_parts(packages: Vec<PackageInfo<'a>>, edges: Vec<Edge>) -> Self {
+        let index = index_by_name(&packages);
+        let mut outgoing = vec![Vec::new(); packages.len()];
+        let mut incoming = vec![Vec::new(); packages.len()];
+        for (idx, edge) in edges.iter().enumerate() {
+            outgoing[edge.from].push(idx);
+            incoming[edge.to].push(idx);
+        }
+        Self {
+            packages,
+            index,
+            edges,
+            outgoing,
+            incoming,
+        }
+    }
+
+    /// Returns the number of packages.
+    pub fn len(&self) -> usize {
+        self.packages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Returns the packages, by their indices.
+    pub fn packages(&self) -> &[PackageInfo<'a>] {
+        &self.packages
+    }
+
+    /// Returns the index of the package `name`; the first one is taken if
+    /// the name is repeated, e.g. across the sync databases.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Returns the edges of the given kinds from the package `node`.
+    pub fn dependencies<'b>(
+        &'b self,
+        node: usize,
+        kinds: &'b [EdgeKind],
+    ) -> impl Iterator<Item = &'b Edge> + 'b {
+        self.outgoing[node]
+            .iter()
+            .map(|&idx| &self.edges[idx])
+            .filter(|edge| kinds.contains(&edge.kind))
+    }
+
+    /// Returns the edges of the given kinds to the package `node`.
+    pub fn dependents<'b>(
+        &'b self,
+        node: usize,
+        kinds: &'b [EdgeKind],
+    ) -> impl Iterator<Item = &'b Edge> + 'b {
+        self.incoming[node]
+            .iter()
+            .map(|&idx| &self.edges[idx])
+            .filter(|edge| kinds.contains(&edge.kind))
+    }
+
+    /// Returns the neighbors of the package `node` in the given direction.
+    fn neighbors<'b>(
+        &'b self,
+        node: usize,
+        kinds: &'b [EdgeKind],
+        direction: Direction,
+    ) -> Box<dyn Iterator<Item = usize> + 'b> {
+        match direction {
+            Direction::Dependencies => Box::new(self.dependencies(node, kinds).map(|x| x.to)),
+            Direction::Dependents => Box::new(self.dependents(node, kinds).map(|x| x.from)),
+        }
+    }
+
+    /// Traverses the graph breadth first from the package `start`, along
+    /// the edges of the given kinds; returns the packages reached, in the
+    /// order of discovery, starting from `start` itself.
+    pub fn bfs(&self, start: usize, kinds: &[EdgeKind], direction: Direction) -> Vec<usize> {
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        let mut order = Vec::new();
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for next in self.neighbors(node, kinds, direction) {
+                if visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        order
+    }
+
+    /// Traverses the graph depth first from the package `start`, along the
+    /// edges of the given kinds; returns the packages reached, in pre-order,
+    /// starting from `start` itself.
+    pub fn dfs(&self, start: usize, kinds: &[EdgeKind], direction: Direction) -> Vec<usize> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![start];
+        let mut order = Vec::new();
+        while let Some(node) = stack.pop() {
+            if !visited.insert(node) {
+                continue;
+            }
+            order.push(node);
+            let next: Vec<_> = self.neighbors(node, kinds, direction).collect();
+            stack.extend(next.into_iter().rev());
+        }
+        order
+    }
+
+    /// Returns whether the package `to` is reachable from the package
+    /// `from`, along the edges of the given kinds.
+    pub fn is_reachable(&self, from: usize, to: usize, kinds: &[EdgeKind]) -> bool {
+        self.bfs(from, kinds, Direction::Dependencies).contains(&to)
+    }
+
+    /// Finds the strongly connected components along the edges of the given
+    /// kinds, with Tarjan's algorithm; a component with more than one
+    /// package is a dependency cycle. The components are returned with the
+    /// dependencies preceding their dependents. The traversal is iterative,
+    /// so that it is safe for large graphs.
+    pub fn strongly_connected(&self, kinds: &[EdgeKind]) -> Vec<Vec<usize>> {
+        const UNVISITED: usize = usize::MAX;
+        let successors: Vec<Vec<usize>> = (0..self.len())
+            .map(|node| self.dependencies(node, kinds).map(|x| x.to).collect())
+            .collect();
+        let mut index = vec![UNVISITED; self.len()];
+        let mut lowlink = vec![0; self.len()];
+        let mut on_stack = vec![false; self.len()];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut counter = 0;
+
+        for root in 0..self.len() {
+            if index[root] != UNVISITED {
+                continue;
+            }
+            // the nodes being visited, with the position of the next successor
+            let mut work = vec![(root, 0)];
+            while let Some(&(node, next)) = work.last() {
+                if index[node] == UNVISITED {
+                    index[node] = counter;
+                    lowlink[node] = counter;
+                    counter += 1;
+                    stack.push(node);
+                    on_stack[node] = true;
+                }
+                if let Some(&successor) = successors[node].get(next) {
+                    work.last_mut().unwrap().1 += 1;
+                    if index[successor] == UNVISITED {
+                        work.push((successor, 0));
+                    } else if on_stack[successor] {
+                        lowlink[node] = lowlink[node].min(index[successor]);
+                    }
+                    continue;
+                }
+                work.pop();
+                if let Some(&(parent, _)) = work.last() {
+                    lowlink[parent] = lowlink[parent].min(lowlink[node]);
+                }
+                if lowlink[node] == index[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+
+    /// Returns all the packages, with the dependencies preceding their
+    /// dependents along the edges of the given kinds; the members of a
+    /// cycle are adjacent, in no particular order.
+    pub fn topological_order(&self, kinds: &[EdgeKind]) -> Vec<usize> {
+        self.strongly_connected(kinds).concat()
+    }
+
+    /// Extracts the subgraph of the given packages, with the edges among
+    /// them; the packages are re-indexed in the given order.
+    pub fn subgraph<I>(&self, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = usize>,
+    {
+        let mut mapping = HashMap::new();
+        let mut packages = Vec::new();
+        for node in nodes {
+            if let Entry::Vacant(entry) = mapping.entry(node) {
+                entry.insert(packages.len());
+                packages.push(self.packages[node].clone());
+            }
+        }
+        let edges = self
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                Some(Edge {
+                    from: *mapping.get(&edge.from)?,
+                    to: *mapping.get(&edge.to)?,
+                    ..edge.clone()
+                })
+            })
+            .collect();
+        Self::from_parts(packages, edges)
+    }
+}
+
+/// Indexes the packages by their names, keeping the first of each name.
+fn index_by_name<'a>(packages: &[PackageInfo<'a>]) -> HashMap<&'a str, usize> {
+    let mut index = HashMap::new();
+    for (idx, pkg) in packages.iter().enumerate() {
+        index.entry(pkg.name).or_insert(idx);
+    }
+    index
+}
@@ -2,7 +2,9 @@
 //! information, including functions to encode and decode relevant data.
 
 use alpm::{decode_signature, Alpm, AlpmList, Dep, IntoAlpmListItem, Package};
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt::Debug;
 
 use crate::reverse_deps::{ReverseDeps, ReverseDepsDatabase, ReverseDepsMap};
@@ -29,7 +31,7 @@ impl<T: Debug> DebugFormat for T {}
 ///
 /// [`package.c`]: https://gitlab.archlinux.org/pacman/pacman/-/blob/master/src/pacman/package.c
 ///
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 #[non_exhaustive]
 pub struct PackageInfo<'a> {
     // #[allow(dead_code)]
@@ -91,7 +93,20 @@ pub struct PackageInfo<'a> {
     ///
     pub key_id: Option<Vec<Box<str>>>,
     pub validated_by: Box<str>,
+
+    /// Note that [`sync_status`][PackageInfo::sync_status] is only known
+    /// when the local and the sync databases are combined, i.e. unless
+    /// `--plain`; it is set by [`PackageFilters::generate_pkg_info`].
+    ///
+    /// [`PackageFilters::generate_pkg_info`]: crate::PackageFilters::generate_pkg_info
+    ///
+    pub sync_status: Option<SyncStatus>,
     pub sync_with: Option<Box<Self>>,
+
+    /// Note that [`closure`][PackageInfo::closure] is only set for the
+    /// packages in the closure of a recursive query, i.e. `--recurse` or
+    /// `--reverse-recurse`.
+    pub closure: Option<ClosureInfo>,
 }
 
 impl<'a> From<&'a Package> for PackageInfo<'a> {
@@ -135,7 +150,9 @@ impl<'a> From<&'a Package> for PackageInfo<'a> {
             signatures: pkg.base64_sig(),
             key_id: None,
             validated_by: pkg.validation().format(),
+            sync_status: None,
             sync_with: None,
+            closure: None,
         }
     }
 }
@@ -152,9 +169,71 @@ impl<'a> PackageInfo<'a> {
     }
 }
 
+/// The position of a package in the closure of a recursive query.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ClosureInfo {
+    /// The number of steps from the queried package, which is at depth 0.
+    /// For `--recurse`, this is the depth at which the package is first
+    /// reached, as the tree is walked depth first.
+    pub depth: u64,
+    /// The package through which this package is first reached by
+    /// `--recurse`; this is [`None`] for the queried package.
+    pub first_parent: Option<ClosureParent>,
+    /// All the packages in the closure of `--recurse` that pull in this
+    /// package, in the order of resolution.
+    pub parents: Vec<ClosureParent>,
+}
+
+/// A package pulling in another package in the closure of `--recurse`.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+pub struct ClosureParent {
+    pub name: String,
+    /// The dependency of the parent satisfied by the package, e.g. `sh` for
+    /// `bash`.
+    pub dep_string: String,
+}
+
+/// The status of an installed package, compared with its counterpart in the
+/// sync databases by [`alpm::vercmp`].
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncStatus {
+    UpToDate,
+    /// The sync package is newer, i.e. the package can be upgraded.
+    Upgradable,
+    /// The installed package is newer, e.g. it is built locally or
+    /// installed from a testing repository.
+    NewerThanRepo,
+    /// The package is not found in the sync databases.
+    Foreign,
+}
+
+impl SyncStatus {
+    /// Compares the versions of an installed package and its sync
+    /// counterpart.
+    ///
+    /// ### Examples:
+    ///
+    /// ```
+    /// # use pacjump::info::SyncStatus;
+    /// assert_eq!(SyncStatus::compare("1.0-1", "1.0-2"), SyncStatus::Upgradable);
+    /// assert_eq!(SyncStatus::compare("1:1.0-1", "2.0-1"), SyncStatus::NewerThanRepo);
+    /// assert_eq!(SyncStatus::compare("1.0-1", "1.0-1"), SyncStatus::UpToDate);
+    /// ```
+    ///
+    pub fn compare(local_version: &str, sync_version: &str) -> Self {
+        match alpm::vercmp(local_version, sync_version) {
+            Ordering::Less => Self::Upgradable,
+            Ordering::Greater => Self::NewerThanRepo,
+            Ordering::Equal => Self::UpToDate,
+        }
+    }
+}
+
 /// A wrapper of the information of a pacman dependency [`Dep`]
 /// for ease of serialization by [`serde`].
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, JsonSchema, Clone, Debug)]
 pub struct DepInfo<'a> {
     pub dep_string: String,
     pub name: &'a str,
@@ -250,7 +329,19 @@ impl<'a> PackageInfo<'a> {
 /// does not work due to rust "orphan rules";
 /// see e.g. <https://github.com/Ixrec/rust-orphan-rules>.
 ///
-#[derive(Serialize, Clone, Debug, derive_more::IntoIterator, derive_more::From)]
+#[derive(
+    Serialize,
+    Deserialize,
+    JsonSchema,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    derive_more::Deref,
+    derive_more::IntoIterator,
+    derive_more::From,
+)]
+#[into_iterator(owned, ref)]
 pub struct PacList<T>(Vec<T>);
 
 impl<'a, T: IntoAlpmListItem> From<AlpmList<'a, T>> for PacList<T> {
@@ -269,3 +360,228 @@ impl<'a> From<AlpmList<'a, &'a Dep>> for PacList<DepInfo<'a>> {
         Self(vector)
     }
 }
+
+/// An owned counterpart of [`PackageInfo`], which does not borrow from the
+/// alpm database and can thus be [`Deserialize`]d, e.g. from a previous dump.
+/// The fields are serialized exactly as in [`PackageInfo`], and the
+/// conversions in both directions are lossless. The fields missing from
+/// the input, e.g. for a dump generated with `--fields`, are left empty;
+/// [`read_dump`] rejects the packages without a `name` or a `version`.
+///
+/// [`read_dump`]: crate::schema::read_dump
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::info::{OwnedPackageInfo, PackageInfo, SyncStatus};
+/// let json = serde_json::json!({
+///     "repository": "local", "name": "foo", "version": "1.0-1",
+///     "description": null, "architecture": "x86_64", "url": null,
+///     "licenses": ["MIT"], "groups": [], "provides": [],
+///     "depends_on": [{
+///         "dep_string": "bar>=2", "name": "bar", "depmod": "Ge",
+///         "version": "2", "description": null, "name_hash": 42,
+///         "satisfier": "bar=2.1-1"
+///     }],
+///     "optional_deps": [], "makedepends": [], "checkdepends": [],
+///     "conflicts_with": [], "replaces": [],
+///     "required_by": ["baz"], "optional_for": [],
+///     "required_by_make": [], "required_by_check": [],
+///     "download_size": 0, "installed_size": 1024, "packager": null,
+///     "build_date": 1700000000, "install_date": 1700000100,
+///     "install_reason": "Explicit", "install_script": false,
+///     "md5_sum": null, "sha_256_sum": null, "signatures": null,
+///     "key_id": null, "validated_by": "SIGNATURE",
+///     "sync_status": "up-to-date", "sync_with": null, "closure": null
+/// });
+/// let owned: OwnedPackageInfo = serde_json::from_value(json.clone())?;
+/// assert_eq!(owned.depends_on[0].name, "bar");
+/// assert_eq!(owned.sync_status, Some(SyncStatus::UpToDate));
+///
+/// // borrow it back and serialize, which gives the same dump:
+/// let borrowed = PackageInfo::from(&owned);
+/// assert_eq!(serde_json::to_value(&borrowed)?, json);
+/// assert_eq!(OwnedPackageInfo::from(borrowed), owned);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct OwnedPackageInfo {
+    pub repository: Option<String>,
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    pub architecture: Option<String>,
+    pub url: Option<String>,
+    pub licenses: PacList<String>,
+    pub groups: PacList<String>,
+    pub provides: PacList<OwnedDepInfo>,
+    pub depends_on: PacList<OwnedDepInfo>,
+    pub optional_deps: PacList<OwnedDepInfo>,
+    pub makedepends: PacList<OwnedDepInfo>,
+    pub checkdepends: PacList<OwnedDepInfo>,
+    pub conflicts_with: PacList<OwnedDepInfo>,
+    pub replaces: PacList<OwnedDepInfo>,
+    pub required_by: ReverseDeps,
+    pub optional_for: ReverseDeps,
+    pub required_by_make: ReverseDeps,
+    pub required_by_check: ReverseDeps,
+    pub download_size: i64,
+    pub installed_size: i64,
+    pub packager: Option<String>,
+    pub build_date: i64,
+    pub install_date: Option<i64>,
+    pub install_reason: Box<str>,
+    pub install_script: bool,
+    pub md5_sum: Option<String>,
+    pub sha_256_sum: Option<String>,
+    pub signatures: Option<String>,
+    pub key_id: Option<Vec<Box<str>>>,
+    pub validated_by: Box<str>,
+    pub sync_status: Option<SyncStatus>,
+    pub sync_with: Option<Box<Self>>,
+    pub closure: Option<ClosureInfo>,
+}
+
+/// An owned counterpart of [`DepInfo`]; see [`OwnedPackageInfo`].
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct OwnedDepInfo {
+    pub dep_string: String,
+    pub name: String,
+    pub depmod: Box<str>,
+    pub version: Option<String>,
+    pub description: Option<String>,
+    pub name_hash: u64,
+    pub satisfier: Option<String>,
+}
+
+impl From<&DepInfo<'_>> for OwnedDepInfo {
+    fn from(dep: &DepInfo<'_>) -> Self {
+        Self {
+            dep_string: dep.dep_string.clone(),
+            name: dep.name.to_string(),
+            depmod: dep.depmod.clone(),
+            version: dep.version.map(String::from),
+            description: dep.description.map(String::from),
+            name_hash: dep.name_hash,
+            satisfier: dep.satisfier.clone(),
+        }
+    }
+}
+
+impl<'a> From<&'a OwnedDepInfo> for DepInfo<'a> {
+    fn from(dep: &'a OwnedDepInfo) -> Self {
+        Self {
+            dep_string: dep.dep_string.clone(),
+            name: &dep.name,
+            depmod: dep.depmod.clone(),
+            version: dep.version.as_deref(),
+            description: dep.description.as_deref(),
+            name_hash: dep.name_hash,
+            satisfier: dep.satisfier.clone(),
+        }
+    }
+}
+
+impl<'a, T, U: From<&'a T>> From<&'a PacList<T>> for PacList<U> {
+    fn from(list: &'a PacList<T>) -> Self {
+        Self(list.iter().map(U::from).collect())
+    }
+}
+
+impl From<&PackageInfo<'_>> for OwnedPackageInfo {
+    /// Copies all the borrowed data of a [`PackageInfo`].
+    fn from(info: &PackageInfo<'_>) -> Self {
+        let owned = |s: Option<&str>| s.map(String::from);
+        let strings = |list: &PacList<&str>| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        Self {
+            repository: owned(info.repository),
+            name: info.name.to_string(),
+            version: info.version.to_string(),
+            description: owned(info.description),
+            architecture: owned(info.architecture),
+            url: owned(info.url),
+            licenses: strings(&info.licenses).into(),
+            groups: strings(&info.groups).into(),
+            provides: (&info.provides).into(),
+            depends_on: (&info.depends_on).into(),
+            optional_deps: (&info.optional_deps).into(),
+            makedepends: (&info.makedepends).into(),
+            checkdepends: (&info.checkdepends).into(),
+            conflicts_with: (&info.conflicts_with).into(),
+            replaces: (&info.replaces).into(),
+            required_by: info.required_by.clone(),
+            optional_for: info.optional_for.clone(),
+            required_by_make: info.required_by_make.clone(),
+            required_by_check: info.required_by_check.clone(),
+            download_size: info.download_size,
+            installed_size: info.installed_size,
+            packager: owned(info.packager),
+            build_date: info.build_date,
+            install_date: info.install_date,
+            install_reason: info.install_reason.clone(),
+            install_script: info.install_script,
+            md5_sum: owned(info.md5_sum),
+            sha_256_sum: owned(info.sha_256_sum),
+            signatures: owned(info.signatures),
+            key_id: info.key_id.clone(),
+            validated_by: info.validated_by.clone(),
+            sync_status: info.sync_status,
+            sync_with: info.sync_with.as_deref().map(|x| Box::new(x.into())),
+            closure: info.closure.clone(),
+        }
+    }
+}
+
+impl From<PackageInfo<'_>> for OwnedPackageInfo {
+    fn from(info: PackageInfo<'_>) -> Self {
+        Self::from(&info)
+    }
+}
+
+impl<'a> From<&'a OwnedPackageInfo> for PackageInfo<'a> {
+    /// Borrows an [`OwnedPackageInfo`] as a [`PackageInfo`], so that it can
+    /// be processed as if it is read from the alpm database.
+    fn from(info: &'a OwnedPackageInfo) -> Self {
+        let strs = |list: &'a PacList<String>| list.iter().map(String::as_str).collect::<Vec<_>>();
+        Self {
+            repository: info.repository.as_deref(),
+            name: &info.name,
+            version: &info.version,
+            description: info.description.as_deref(),
+            architecture: info.architecture.as_deref(),
+            url: info.url.as_deref(),
+            licenses: strs(&info.licenses).into(),
+            groups: strs(&info.groups).into(),
+            provides: (&info.provides).into(),
+            depends_on: (&info.depends_on).into(),
+            optional_deps: (&info.optional_deps).into(),
+            makedepends: (&info.makedepends).into(),
+            checkdepends: (&info.checkdepends).into(),
+            conflicts_with: (&info.conflicts_with).into(),
+            replaces: (&info.replaces).into(),
+            required_by: &info.required_by,
+            optional_for: &info.optional_for,
+            required_by_make: &info.required_by_make,
+            required_by_check: &info.required_by_check,
+            download_size: info.download_size,
+            installed_size: info.installed_size,
+            packager: info.packager.as_deref(),
+            build_date: info.build_date,
+            install_date: info.install_date,
+            install_reason: info.install_reason.clone(),
+            install_script: info.install_script,
+            md5_sum: info.md5_sum.as_deref(),
+            sha_256_sum: info.sha_256_sum.as_deref(),
+            signatures: info.signatures.as_deref(),
+            key_id: info.key_id.clone(),
+            validated_by: info.validated_by.clone(),
+            sync_status: info.sync_status,
+            sync_with: info.sync_with.as_deref().map(|x| Box::new(x.into())),
+            closure: info.closure.clone(),
+        }
+    }
+}
@@ -1,18 +1,46 @@
+pub mod conf;
+pub mod cycles;
+pub mod diff;
+pub mod fields;
+pub mod graph;
 pub mod info;
+pub mod orphans;
+pub mod output;
 pub mod recurse_deps;
 pub mod reverse_deps;
+pub mod sbom;
+pub mod schema;
 pub mod siglevel;
+pub mod source;
+pub mod upgrades;
+pub mod why;
 
 use alpm::{Alpm, Db, Package, PackageReason};
-use clap::Parser;
-use info::PackageInfo;
+use clap::{ArgGroup, Parser, Subcommand};
+use conf::{ConfOverrides, PacmanConf, DEFAULT_CONF_PATH};
+use diff::DiffArgs;
+use fields::FieldSelection;
+use indexmap::IndexSet;
+use info::{PackageInfo, SyncStatus};
+use output::OutputFormat;
+use recurse_deps::recurse_closure;
 use reverse_deps::ReverseDepsDatabase;
+use siglevel::{default_siglevel, repo_siglevel};
+use std::path::PathBuf;
 
 /// Available filters for pacman packages, exposed
 /// through the command line interface.
 #[derive(Debug, Parser)]
-#[command(about)]
+#[command(about, args_conflicts_with_subcommands = true)]
+#[command(group(
+    ArgGroup::new("graph")
+        .args(["recurse", "reverse_recurse", "why", "cycles"])
+        .multiple(true)
+))]
 pub struct PackageFilters {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Query the sync databases; by default only the local database
     /// (of currently installed packages) is queried
     #[arg(long)]
@@ -31,19 +59,229 @@ pub struct PackageFilters {
 
     /// Recursively query the dependencies of the given package;
     /// implies `--all`
-    #[arg(long)]
+    #[arg(long, group = "closure")]
     pub recurse: Option<String>,
 
-    /// `--recurse` installed optional dependencies as well
-    #[arg(long, requires = "recurse")]
+    /// Recursively query the packages that depend on the given package,
+    /// i.e. the reverse of `--recurse`; only the installed dependents are
+    /// followed, unless `--sync`
+    #[arg(long, value_name = "PKG", group = "closure")]
+    pub reverse_recurse: Option<String>,
+
+    /// Explain why the given package is installed, by the chains of
+    /// dependencies from the explicitly installed packages down to it; one
+    /// of the shortest chains is shown for each explicit package
+    #[arg(
+        long,
+        value_name = "PKG",
+        group = "closure",
+        conflicts_with_all = ["sync", "format", "fields", "envelope"]
+    )]
+    pub why: Option<String>,
+
+    /// Show all the chains found by `--why`, instead of the shortest ones
+    #[arg(long, requires = "why")]
+    pub all_paths: bool,
+
+    /// Query only the installed packages that are not found in the sync
+    /// databases, e.g. those from the AUR or built locally; the same as
+    /// `pacman -Qm`
+    #[arg(long, conflicts_with_all = ["sync", "native", "closure"])]
+    pub foreign: bool,
+
+    /// Query only the installed packages that are found in the sync
+    /// databases; the same as `pacman -Qn`
+    #[arg(long, conflicts_with_all = ["sync", "closure"])]
+    pub native: bool,
+
+    /// Report the orphans, i.e. the packages installed as dependencies that
+    /// are no longer required, including those only wanted optionally and
+    /// the cycles of packages that only require each other
+    #[arg(
+        long,
+        conflicts_with_all = ["sync", "closure", "foreign", "native", "format", "fields", "envelope"]
+    )]
+    pub orphans: bool,
+
+    /// List the installed packages that can be upgraded from the sync
+    /// databases, as they are synced, with the old and new versions and the
+    /// size changes; the same as `pacman -Qu`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "sync", "plain", "closure", "orphans", "format", "fields", "envelope"
+        ]
+    )]
+    pub upgradable: bool,
+
+    /// Report the dependency cycles, i.e. the groups of packages that depend
+    /// on each other, with the dependency strings forming each cycle; the
+    /// installed packages are inspected, or the sync packages with `--sync`
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "closure", "orphans", "upgradable", "foreign", "native", "format", "fields", "envelope"
+        ]
+    )]
+    pub cycles: bool,
+
+    /// Exit with an error if `--cycles` finds any cycle, e.g. to check a
+    /// repository in CI
+    #[arg(long, requires = "cycles")]
+    pub check: bool,
+
+    /// `--recurse` installed optional dependencies as well; for
+    /// `--reverse-recurse` and `--why`, follow the packages that optionally
+    /// depend on the given package as well; for `--cycles`, follow the
+    /// optional dependencies as well
+    #[arg(long, requires = "graph")]
     pub optional: bool,
 
-    /// `--recurse` dependencies, but only prints package names and versions
+    /// `--recurse` the build dependencies, i.e. the `makedepends` and
+    /// `checkdepends` of the given package, besides its runtime
+    /// dependencies; the runtime dependencies are followed below. This is
+    /// best used with `--sync`, as the local database may lack the build
+    /// dependencies
     #[arg(long, requires = "recurse")]
+    pub build: bool,
+
+    /// `--recurse` or `--reverse-recurse`, but only prints package names
+    /// and versions; for `--why`, only prints the chains
+    #[arg(long, requires = "closure")]
     pub summary: bool,
+
+    /// Output JSON Lines (NDJSON), one package per line, as they are
+    /// generated; by default a single JSON array is printed at the end.
+    /// This is a shorthand for `--format=ndjson`
+    #[arg(long, conflicts_with_all = ["summary", "format"])]
+    pub ndjson: bool,
+
+    /// The output format of the packages; `dot` and `tree` require
+    /// `--recurse`
+    #[arg(
+        long,
+        value_enum,
+        default_value_t,
+        conflicts_with = "summary",
+        requires_ifs([("dot", "recurse"), ("tree", "recurse")])
+    )]
+    pub format: OutputFormat,
+
+    /// Truncate the `tree` output below the given depth
+    #[arg(long, value_name = "N", requires = "recurse")]
+    pub max_depth: Option<u64>,
+
+    /// Wrap the `json` output in an envelope, with the schema version and
+    /// the information on the inspected system
+    #[arg(long)]
+    pub envelope: bool,
+
+    /// Print the JSON Schema of the `json` output and exit
+    #[arg(long, exclusive = true)]
+    pub print_schema: bool,
+
+    /// The columns of the `csv` and `tsv` formats, separated by commas,
+    /// e.g. `name,version,depends_on.name`; by default all top-level
+    /// fields except `sync_with`
+    #[arg(
+        long,
+        value_name = "COLUMNS",
+        value_delimiter = ',',
+        value_parser = parse_column
+    )]
+    pub columns: Vec<String>,
+
+    /// The separator to join lists, e.g. `licenses`, within a single cell
+    /// of the `csv` and `tsv` formats; a space by default
+    #[arg(long, value_name = "SEP")]
+    pub list_separator: Option<String>,
+
+    /// Output only the selected fields, separated by commas; nested fields
+    /// are selected by their paths, e.g. `name,sync_with.version`
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        value_parser = parse_fields,
+        conflicts_with = "summary"
+    )]
+    pub fields: Option<FieldSelection>,
+
+    /// Read an alternate pacman configuration file
+    #[arg(long, value_name = "PATH", default_value = DEFAULT_CONF_PATH)]
+    pub config: PathBuf,
+
+    /// Inspect an alternate installation root, e.g. a chroot or a mounted
+    /// image; overrides `RootDir` in the configuration
+    #[arg(long, value_name = "PATH")]
+    pub root: Option<String>,
+
+    /// Read the databases from an alternate location; overrides `DBPath`
+    /// in the configuration
+    #[arg(long, value_name = "PATH")]
+    pub dbpath: Option<String>,
+
+    /// Read the packages from a JSON dump of `pacjump`, instead of the
+    /// pacman databases; the reverse dependencies are regenerated from the
+    /// packages in the dump
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["sync", "plain", "envelope", "root", "dbpath", "config"]
+    )]
+    pub from_dump: Option<PathBuf>,
+}
+
+/// Subcommands that work on the dumps, instead of the pacman databases.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    Diff(DiffArgs),
+}
+
+/// Parses the `--fields`, whose paths must name the fields of
+/// [`PackageInfo`], as they are listed in its JSON Schema.
+fn parse_fields(fields: &str) -> anyhow::Result<FieldSelection> {
+    let selection: FieldSelection = fields.parse()?;
+    for path in fields.split(',').map(str::trim) {
+        schema::check_field_path(path)?;
+    }
+    Ok(selection)
+}
+
+/// Parses a column of the `--columns`, whose path must name a field of
+/// [`PackageInfo`], as it is listed in its JSON Schema.
+fn parse_column(column: &str) -> anyhow::Result<String> {
+    let column = column.trim();
+    schema::check_field_path(column)?;
+    Ok(column.to_string())
 }
 
 impl PackageFilters {
+    /// Returns the output format, taking the shorthand options into account.
+    pub fn output_format(&self) -> OutputFormat {
+        match self.ndjson {
+            true => OutputFormat::Ndjson,
+            false => self.format,
+        }
+    }
+
+    /// Returns whether a package is selected, according to whether it is
+    /// explicitly installed.
+    pub fn selects(&self, explicit: bool) -> bool {
+        // only focus on explicitly installed packages
+        let closure =
+            self.recurse.is_some() || self.reverse_recurse.is_some() || self.why.is_some();
+        closure || self.orphans || self.upgradable || self.cycles || self.all || explicit
+    }
+
+    /// Returns whether a package is selected by `--foreign` or `--native`,
+    /// according to whether it is found in the sync databases.
+    pub fn selects_locality(&self, native: bool) -> bool {
+        match native {
+            true => !self.foreign,
+            false => !self.native,
+        }
+    }
+
     /// Applies an instance of [`PackageFilters`] to an [`alpm::Package`], and
     /// returns either the desired [`info::PackageInfo`] or an error.
     pub fn generate_pkg_info<'a>(
@@ -52,14 +290,26 @@ impl PackageFilters {
         pkg: &'a Package,
         reverse_deps: &'a ReverseDepsDatabase,
     ) -> anyhow::Result<PackageInfo<'a>> {
-        // only focus on explicitly installed packages
-        if self.recurse.is_none() && !self.all && pkg.reason() != PackageReason::Explicit {
+        if !self.selects(pkg.reason() == PackageReason::Explicit) {
             anyhow::bail!("{:?} not explicitly installed, skipped", pkg);
         }
         let mut pkg_info = PackageInfo::new(handle, pkg, self.sync);
         if !self.plain {
             pkg_info = self.enrich_pkg_info(handle, pkg_info)
         }
+        if self.foreign || self.native {
+            let native = match pkg_info.sync_status {
+                Some(sync_status) => sync_status != SyncStatus::Foreign,
+                // not enriched, e.g. with `--plain`
+                None => find_in_databases(get_databases(handle, true), pkg.name()).is_ok(),
+            };
+            if !self.selects_locality(native) {
+                anyhow::bail!("{:?} filtered by locality, skipped", pkg);
+            }
+        }
+        if !self.selects_sync_status(pkg_info.sync_status) {
+            anyhow::bail!("{:?} not upgradable, skipped", pkg);
+        }
         return Ok(pkg_info.add_reverse_deps(reverse_deps));
     }
 
@@ -73,28 +323,202 @@ impl PackageFilters {
             match find_in_databases(complementary_databases, pkg_info.name.to_string()) {
                 Err(msg) => {
                     eprintln!("{msg}");
-                    return pkg_info;
+                    return match self.sync {
+                        true => pkg_info,
+                        false => PackageInfo {
+                            sync_status: Some(SyncStatus::Foreign),
+                            ..pkg_info
+                        },
+                    };
                 }
                 Ok(pkg) => pkg,
             };
         let complemetary_info = PackageInfo::new(handle, complementary_pkg, !self.sync);
         if self.sync {
-            return pkg_info.add_local_info(complemetary_info);
+            let sync_status = SyncStatus::compare(complemetary_info.version, pkg_info.version);
+            return PackageInfo {
+                sync_status: Some(sync_status),
+                ..pkg_info.add_local_info(complemetary_info)
+            };
         }
         // otherwise, the input `pkg` is local:
         let local_info = pkg_info;
         let sync_info = complemetary_info;
+        let sync_status = SyncStatus::compare(local_info.version, sync_info.version);
 
-        return match true
+        let pkg_info = match true
             && local_info.packager == sync_info.packager
             && local_info.version == sync_info.version
         {
             true => sync_info.add_local_info(local_info),
             false => local_info.add_sync_info(sync_info),
         };
+        return PackageInfo {
+            sync_status: Some(sync_status),
+            ..pkg_info
+        };
+    }
+
+    /// Returns whether a package is selected by `--upgradable`, according to
+    /// its [`SyncStatus`].
+    pub fn selects_sync_status(&self, sync_status: Option<SyncStatus>) -> bool {
+        !self.upgradable || sync_status == Some(SyncStatus::Upgradable)
+    }
+}
+
+/// A builder of a [`Session`], which specifies where the pacman
+/// configuration and databases are located.
+///
+/// ### Examples:
+///
+/// ```no_run
+/// # use pacjump::{PackageFilters, Session};
+/// # use clap::{Parser, Subcommand};
+/// #
+/// let session = Session::builder().root("/mnt/chroot").build()?;
+/// let filters = PackageFilters::parse_from(["pacjump", "--all"]);
+/// for pkg in session.packages(&filters) {
+///     println!("{} {}", pkg.name, pkg.version);
+/// }
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct SessionBuilder {
+    config: PathBuf,
+    overrides: ConfOverrides,
+}
+
+impl Default for SessionBuilder {
+    fn default() -> Self {
+        Self {
+            config: DEFAULT_CONF_PATH.into(),
+            overrides: ConfOverrides::default(),
+        }
+    }
+}
+
+impl SessionBuilder {
+    /// Reads an alternate pacman configuration file; by default it is
+    /// [`DEFAULT_CONF_PATH`].
+    pub fn config<P: Into<PathBuf>>(self, path: P) -> Self {
+        Self {
+            config: path.into(),
+            ..self
+        }
+    }
+
+    /// Inspects an alternate installation root; overrides `RootDir`.
+    pub fn root<S: Into<String>>(mut self, root: S) -> Self {
+        self.overrides.root_dir = Some(root.into());
+        self
+    }
+
+    /// Reads the databases from an alternate location; overrides `DBPath`.
+    pub fn dbpath<S: Into<String>>(mut self, db_path: S) -> Self {
+        self.overrides.db_path = Some(db_path.into());
+        self
+    }
+
+    /// Reads the pacman configuration, initializes the [`Alpm`] handle with
+    /// all the sync databases registered, and generates the reverse
+    /// dependencies.
+    pub fn build(self) -> anyhow::Result<Session> {
+        let conf = PacmanConf::from_file_with_overrides(&self.config, &self.overrides)?;
+        let handle = init_alpm(&conf)?;
+        let reverse_deps = ReverseDepsDatabase::from(&handle);
+        Ok(Session {
+            conf,
+            handle,
+            reverse_deps,
+        })
+    }
+}
+
+/// A ready-to-query pacman session, which owns the [`Alpm`] handle and the
+/// [`ReverseDepsDatabase`]; the [`PackageInfo`]s are borrowed from it.
+/// This is constructed with a [`SessionBuilder`].
+pub struct Session {
+    conf: PacmanConf,
+    handle: Alpm,
+    reverse_deps: ReverseDepsDatabase,
+}
+
+impl Session {
+    /// Makes a new [`SessionBuilder`] with the default configuration.
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    /// Returns the pacman configuration of this session.
+    pub fn conf(&self) -> &PacmanConf {
+        &self.conf
+    }
+
+    /// Returns the underlying [`Alpm`] handle.
+    pub fn handle(&self) -> &Alpm {
+        &self.handle
+    }
+
+    /// Returns the reverse dependencies of all the sync and installed packages.
+    pub fn reverse_deps(&self) -> &ReverseDepsDatabase {
+        &self.reverse_deps
+    }
+
+    /// Returns the sync or local databases, as specified by the filters.
+    pub fn databases(&self, pkg_filters: &PackageFilters) -> Box<[&Db]> {
+        get_databases(&self.handle, pkg_filters.sync)
+    }
+
+    /// Enumerates all packages that pass the filters. The [`PackageInfo`]s
+    /// are generated lazily, so that they can be streamed.
+    pub fn packages<'a>(
+        &'a self,
+        pkg_filters: &'a PackageFilters,
+    ) -> impl Iterator<Item = PackageInfo<'a>> + 'a {
+        self.databases(pkg_filters)
+            .into_vec()
+            .into_iter()
+            .flat_map(move |db| {
+                db.pkgs().into_iter().filter_map(move |pkg| {
+                    pkg_filters
+                        .generate_pkg_info(&self.handle, pkg, &self.reverse_deps)
+                        .ok()
+                })
+            })
+    }
+
+    /// Recursively collects the dependencies of the package `name`. Returns
+    /// the `name=version` of all packages in the closure, in the order of
+    /// discovery, and their [`PackageInfo`]s, with the dependencies
+    /// preceding their dependents.
+    pub fn recurse(
+        &self,
+        pkg_filters: &PackageFilters,
+        name: &str,
+    ) -> anyhow::Result<(IndexSet<String>, Vec<PackageInfo<'_>>)> {
+        recurse_closure(self, pkg_filters, name)
     }
 }
 
+/// Initializes an [`Alpm`] handle from a [`PacmanConf`], and registers all
+/// the sync databases of the configured repositories.
+pub fn init_alpm(conf: &PacmanConf) -> anyhow::Result<Alpm> {
+    let default_siglevel = default_siglevel(conf)?;
+    let mut handle = Alpm::new(conf.root_dir.as_str(), conf.db_path.as_str())?;
+
+    // register sync databases from pacman.conf
+    for repo in &conf.repos {
+        let sig_level = repo_siglevel(repo, default_siglevel)?;
+        let db = handle.register_syncdb_mut(repo.name.as_str(), sig_level)?;
+        for server in &repo.servers {
+            db.add_server(server.as_str())?;
+        }
+        db.set_usage(repo.usage)?;
+    }
+    Ok(handle)
+}
+
 /// Returns a <code>[Box]<[&[alpm::Db]]></code> of the sync or local databases.
 pub fn get_databases<'a>(handle: &'a Alpm, sync: bool) -> Box<[&'a Db]> {
     match sync {
@@ -125,32 +549,3 @@ where
     }
     anyhow::bail!("{:?} not found in the sync databases", &name)
 }
-
-/// Reads `pacman.conf` via the cli `pacman-conf`. The arguments are directly
-/// passed into [`std::process::Command::args`] and the result is parsed into
-/// a [`String`].
-pub fn read_conf<I, S>(args: I) -> Result<String, std::io::Error>
-where
-    I: IntoIterator<Item = S>,
-    S: AsRef<std::ffi::OsStr>,
-{
-    let cmd_out = std::process::Command::new("pacman-conf")
-        .args(args)
-        .env("LC_ALL", "C.UTF-8")
-        .env("LANGUAGE", "C.UTF-8")
-        /*
-            ^ making sure that user locales do not interfere;
-            see: https://sourceware.org/bugzilla/show_bug.cgi?id=16621
-        */
-        .output()?
-        .stdout;
-
-    let out_string = String::from_utf8_lossy(&cmd_out).to_string();
-
-    let trimmed_string = match out_string.strip_suffix('\n') {
-        Some(x) => x.to_string(),
-        None => out_string,
-    };
-
-    Ok(trimmed_string)
-}
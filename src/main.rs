@@ -1,74 +1,216 @@
+use pacjump::cycles::CycleReport;
+use pacjump::diff::{DiffArgs, DiffReport};
+use pacjump::fields::Projection;
+use pacjump::graph::DependencyGraph;
 use pacjump::info::PackageInfo;
-use pacjump::recurse_deps::recurse_dependencies;
-use pacjump::reverse_deps::ReverseDepsDatabase;
+use pacjump::orphans::OrphanReport;
+use pacjump::output::{
+    format_size, write_dot, write_json_lines, write_table, write_tree, OutputFormat, TableOptions,
+};
+use pacjump::recurse_deps::{recurse_closure, reverse_closure};
+use pacjump::sbom::{write_cyclonedx, write_spdx_json, write_spdx_tag_value};
+use pacjump::schema::{dump_schema, read_dump_file, Envelope};
 use pacjump::siglevel::{default_siglevel, repo_siglevel};
-use pacjump::{find_in_databases, get_databases, read_conf, PackageFilters};
+use pacjump::source::{DumpSource, PackageSource};
+use pacjump::upgrades::Upgrade;
+use pacjump::why::find_chains;
+use pacjump::{Command, PackageFilters, Session};
 
-use alpm::Alpm;
 use clap::Parser;
-use indexmap::IndexSet;
 
 /// Dumps json data of the explicitly installed pacman packages.
 /// Local packages are matched against the sync databases,
 /// and upstream info is added to the output.
 fn main() -> anyhow::Result<()> {
-    #[cfg(debug_assertions)]
-    #[cfg(feature = "backtrace-overflow")]
-    unsafe {
-        backtrace_on_stack_overflow::enable()
-    };
     let pkg_filters = PackageFilters::parse();
 
-    let root = read_conf(["RootDir"]).unwrap_or("/".into());
-    let db_path = read_conf(["DBPath"]).unwrap_or("/var/lib/pacman/".into());
-    let all_repos = read_conf(["--repo-list"]).unwrap_or(["core", "extra", "multilib"].join("\n"));
-    eprintln!("RootDir: {root}");
-    eprintln!("DBPath: {db_path}");
+    if let Some(Command::Diff(diff_args)) = &pkg_filters.command {
+        return diff(diff_args);
+    }
+    if pkg_filters.print_schema {
+        let schema = serde_json::to_string_pretty(&dump_schema())?;
+        println!("{}", schema);
+        return Ok(());
+    }
+    if pkg_filters.envelope && pkg_filters.output_format() != OutputFormat::Json {
+        anyhow::bail!("--envelope is only supported for the json format");
+    }
+    let projected_formats = [
+        OutputFormat::Json,
+        OutputFormat::Ndjson,
+        OutputFormat::Csv,
+        OutputFormat::Tsv,
+    ];
+    if pkg_filters.fields.is_some() && !projected_formats.contains(&pkg_filters.output_format()) {
+        anyhow::bail!("--fields is only supported for the json, ndjson, csv and tsv formats");
+    }
+    let table_formats = [OutputFormat::Csv, OutputFormat::Tsv];
+    if (!pkg_filters.columns.is_empty() || pkg_filters.list_separator.is_some())
+        && !table_formats.contains(&pkg_filters.output_format())
+    {
+        anyhow::bail!(
+            "--columns and --list-separator are only supported for the csv and tsv formats"
+        );
+    }
+    if pkg_filters.max_depth.is_some() && pkg_filters.output_format() != OutputFormat::Tree {
+        anyhow::bail!("--max-depth is only supported for the tree format");
+    }
 
-    let default_siglevel = default_siglevel();
-    eprintln!("SigLevel::{default_siglevel:?}");
-    eprintln!("");
+    match &pkg_filters.from_dump {
+        Some(path) => {
+            let dump = DumpSource::from_file(path)?;
+            eprintln!(
+                "# loaded {} packages from the dump",
+                dump.owned_packages().len()
+            );
+            eprintln!();
+            run(&pkg_filters, None, &dump)?
+        }
+        None => {
+            let mut builder = Session::builder().config(&pkg_filters.config);
+            if let Some(root) = &pkg_filters.root {
+                builder = builder.root(root);
+            }
+            if let Some(dbpath) = &pkg_filters.dbpath {
+                builder = builder.dbpath(dbpath);
+            }
+            let session = builder.build()?;
+            print_session(&session)?;
+            run(&pkg_filters, Some(&session), &session)?
+        }
+    }
 
-    let handle = &Alpm::new(root, db_path).unwrap();
+    eprintln!();
+    eprintln!("# all done.");
+    Ok(())
+}
+
+/// Prints the configuration of the session, and the number of the reverse
+/// dependencies generated.
+fn print_session(session: &Session) -> anyhow::Result<()> {
+    let conf = session.conf();
+    eprintln!("RootDir: {}", conf.root_dir);
+    eprintln!("DBPath: {}", conf.db_path);
+
+    let default_siglevel = default_siglevel(conf)?;
+    eprintln!("SigLevel::{default_siglevel:?}");
+    eprintln!();
 
-    // register sync databases from pacman.conf
     eprintln!("--repo-list:");
-    for repo in all_repos.split_terminator('\n') {
-        let sig_level = repo_siglevel(repo, default_siglevel);
-        handle.register_syncdb(repo, sig_level).unwrap();
-        eprintln!("{repo}: SigLevel::{sig_level:?}");
+    for repo in &conf.repos {
+        let sig_level = repo_siglevel(repo, default_siglevel)?;
+        eprintln!("{}: SigLevel::{sig_level:?}", repo.name);
     }
-    eprintln!("");
+    eprintln!();
 
-    eprintln!("# generating reverse dependencies ...");
-    let reverse_deps = ReverseDepsDatabase::from(handle);
     eprintln!(
-        "# done. Required-by pkgs: {}",
-        reverse_deps.required_by.len()
+        "# generated reverse dependencies. Required-by pkgs: {}",
+        session.reverse_deps().required_by.len()
     );
-    eprintln!("");
-
-    let databases = get_databases(handle, pkg_filters.sync);
-    let all_packages: Vec<PackageInfo<'_>> = if let Some(name) = &pkg_filters.recurse {
-        let pkg = find_in_databases(databases.clone(), name)?;
-        let pkg_info = pkg_filters.generate_pkg_info(handle, pkg, &reverse_deps)?;
-        let mut deps_set = IndexSet::new();
-        let mut deps_pkgs = Vec::new();
-        let _ = recurse_dependencies(
-            handle,
-            databases,
-            &pkg_filters,
-            &reverse_deps,
-            pkg_info,
-            0,
-            &mut deps_set,
-            &mut deps_pkgs,
+    eprintln!();
+    Ok(())
+}
+
+/// Compares two dumps, and prints the report.
+fn diff(diff_args: &DiffArgs) -> anyhow::Result<()> {
+    let old = read_dump_file(&diff_args.old)?;
+    let new = read_dump_file(&diff_args.new)?;
+    let report = DiffReport::new(&old, &new);
+    match diff_args.summary {
+        true => println!("{report}"),
+        false => {
+            eprintln!("{report}");
+            println!("{}", serde_json::to_string(&report)?);
+        }
+    }
+    Ok(())
+}
+
+/// Runs the query over a [`PackageSource`]; the [`Session`] is absent if the
+/// packages are read from a dump.
+fn run<S: PackageSource>(
+    pkg_filters: &PackageFilters,
+    session: Option<&Session>,
+    source: &S,
+) -> anyhow::Result<()> {
+    if pkg_filters.orphans {
+        let report = OrphanReport::new(source, pkg_filters);
+        eprintln!();
+        eprintln!(
+            "# orphans: {}, optional only: {}, cycles: {}",
+            report.orphans.len(),
+            report.optional_only.len(),
+            report.cycles.len()
         );
+        println!("{}", serde_json::to_string(&report)?);
+        return Ok(());
+    }
+    if pkg_filters.cycles {
+        let report = CycleReport::new(source, pkg_filters);
+        eprintln!();
+        for cycle in &report.cycles {
+            eprintln!("{cycle}");
+        }
+        eprintln!("# cycles: {}", report.cycles.len());
+        println!("{}", serde_json::to_string(&report)?);
+        if pkg_filters.check && !report.cycles.is_empty() {
+            anyhow::bail!("found {} dependency cycles", report.cycles.len());
+        }
+        return Ok(());
+    }
+    if pkg_filters.upgradable {
+        let upgrades: Vec<_> = source
+            .packages(pkg_filters)
+            .filter_map(|pkg| Upgrade::new(&pkg))
+            .collect();
+        eprintln!();
+        for upgrade in &upgrades {
+            eprintln!("{upgrade}");
+        }
+        eprintln!(
+            "# upgradable: {}, download size: {}, installed size change: {}",
+            upgrades.len(),
+            format_size(upgrades.iter().map(|x| x.download_size).sum()),
+            format_size(upgrades.iter().map(|x| x.installed_size_delta).sum()),
+        );
+        println!("{}", serde_json::to_string(&upgrades)?);
+        return Ok(());
+    }
+    if let Some(name) = &pkg_filters.why {
+        let (chains, truncated) = find_chains(source, pkg_filters, name, pkg_filters.all_paths)?;
+        if truncated {
+            eprintln!("# too many paths, stopped after {} chains", chains.len());
+        }
+        if pkg_filters.summary {
+            for chain in &chains {
+                println!("{chain}");
+            }
+            return Ok(());
+        }
+        eprintln!();
+        for chain in &chains {
+            eprintln!("{chain}");
+        }
+        eprintln!("# '{}' is pulled in by {} chains", name, chains.len());
+        println!("{}", serde_json::to_string(&chains)?);
+        return Ok(());
+    }
+    if let Some(name) = &pkg_filters.recurse {
+        let (deps_set, deps_pkgs) = recurse_closure(source, pkg_filters, name)?;
 
-        eprintln!("");
+        eprintln!();
         eprintln!("{:#?}", deps_set);
         eprintln!("# '{}' closure: {} packages", name, deps_set.len());
-        eprintln!("");
+        if pkg_filters.build {
+            // the package itself is not a part of its build environment
+            let size = deps_pkgs
+                .iter()
+                .filter(|pkg| pkg.name != name)
+                .map(|pkg| pkg.installed_size)
+                .sum();
+            eprintln!("# build environment: {}", format_size(size));
+        }
+        eprintln!();
 
         if pkg_filters.summary {
             for dep in deps_set {
@@ -77,34 +219,100 @@ fn main() -> anyhow::Result<()> {
             return Ok(());
         }
 
-        deps_pkgs.reverse();
-        deps_pkgs
+        print_packages(pkg_filters, session, source, deps_pkgs)
+    } else if let Some(name) = &pkg_filters.reverse_recurse {
+        let dependents = reverse_closure(source, pkg_filters, name)?;
+
+        eprintln!();
+        eprintln!(
+            "# '{}' reverse closure: {} packages",
+            name,
+            dependents.len()
+        );
+        eprintln!();
+
+        if pkg_filters.summary {
+            for pkg in dependents {
+                println!("{}={}", pkg.name, pkg.version)
+            }
+            return Ok(());
+        }
+
+        print_packages(pkg_filters, session, source, dependents)
     } else {
         eprintln!("# enumerating all packages ...");
-        databases
-            .iter()
-            .map(|db| {
+        if let Some(session) = session {
+            for db in session.databases(pkg_filters).iter() {
                 eprintln!("{}: {}", db.name(), db.pkgs().len());
-                db.pkgs()
-                    .iter()
-                    .filter_map(|pkg| {
-                        pkg_filters
-                            .generate_pkg_info(handle, pkg, &reverse_deps)
-                            .ok()
-                    })
-                    .collect::<Vec<_>>()
-            })
-            .flatten()
-            .collect() // flattened list of packages
+            }
+        }
+        print_packages(pkg_filters, session, source, source.packages(pkg_filters))
+    }
+}
+
+/// Prints the packages to stdout in the desired output format; the packages
+/// are streamed, except for the JSON array. The dependencies of the SBOMs
+/// are resolved against the [`PackageSource`].
+fn print_packages<'a, S, I>(
+    pkg_filters: &PackageFilters,
+    session: Option<&Session>,
+    source: &S,
+    packages: I,
+) -> anyhow::Result<()>
+where
+    S: PackageSource,
+    I: IntoIterator<Item = PackageInfo<'a>>,
+{
+    let stdout = &mut std::io::stdout().lock();
+    let fields = pkg_filters.fields.clone().unwrap_or_default();
+    let projected = |packages: I| {
+        packages
+            .into_iter()
+            .map(|pkg| Projection::new(pkg, &fields))
+    };
+    let graph = |packages: I| {
+        DependencyGraph::from_packages(source, pkg_filters, packages.into_iter().collect())
     };
+    let table_options = |delimiter| TableOptions {
+        delimiter,
+        columns: pkg_filters.columns.clone(),
+        list_separator: pkg_filters.list_separator.clone().unwrap_or(" ".into()),
+    };
+    let count = match pkg_filters.output_format() {
+        OutputFormat::Json => {
+            let all_packages: Vec<_> = projected(packages).collect();
 
-    eprintln!("# done. Serializing ...");
-    eprintln!("");
+            eprintln!("# done. Serializing ...");
+            eprintln!();
 
-    let json = serde_json::to_string(&all_packages).expect("failed serializing json");
-    println!("{}", json);
+            let json = match session.filter(|_| pkg_filters.envelope) {
+                Some(session) => serde_json::to_string(&Envelope::new(session, all_packages)),
+                None => serde_json::to_string(&all_packages),
+            }?;
+            println!("{}", json);
+            return Ok(());
+        }
+        OutputFormat::Ndjson => {
+            eprintln!("# streaming json lines ...");
+            eprintln!();
+            write_json_lines(stdout, projected(packages))?
+        }
+        OutputFormat::Csv => write_table(stdout, projected(packages), &table_options(','))?,
+        OutputFormat::Tsv => write_table(stdout, projected(packages), &table_options('\t'))?,
+        OutputFormat::Dot => {
+            let packages: Vec<_> = packages.into_iter().collect();
+            write_dot(stdout, &packages)?
+        }
+        OutputFormat::Tree => {
+            let packages: Vec<_> = packages.into_iter().collect();
+            write_tree(stdout, &packages, pkg_filters.max_depth)?
+        }
+        OutputFormat::Cyclonedx => write_cyclonedx(stdout, &graph(packages))?,
+        OutputFormat::Spdx => write_spdx_tag_value(stdout, &graph(packages))?,
+        OutputFormat::SpdxJson => write_spdx_json(stdout, &graph(packages))?,
+    };
 
-    eprintln!("");
-    eprintln!("# all done.");
+    eprintln!();
+    eprintln!("# done. Packages: {count}");
     Ok(())
 }
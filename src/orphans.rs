@@ -0,0 +1,139 @@
+//! A module that finds the orphans among the installed packages, i.e. the
+//! packages installed as dependencies which are no longer required, as
+//! `pacman -Qdt` does. In addition, the packages kept only by optional
+//! dependencies are told apart, and the orphan cycles, i.e. groups of
+//! packages that only require each other, are detected; these are missed by
+//! `pacman -Qdt` and `pacman -Qdtt` alike.
+//!
+//! The dependencies are resolved to the installed satisfiers in a
+//! [`DependencyGraph`], so that the provisions, e.g. `sh` by `bash`, are
+//! taken into account, and the query works over a dump as well.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::graph::{DependencyGraph, EdgeKind};
+use crate::source::PackageSource;
+use crate::PackageFilters;
+
+/// A package installed as a dependency, which is only wanted by the optional
+/// dependencies of other packages.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct OptionalOrphan {
+    pub name: String,
+    pub optional_for: Vec<String>,
+}
+
+/// The orphans among the installed packages, sorted by names.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::orphans::OrphanReport;
+/// # use pacjump::source::DumpSource;
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "app", "version": "1.0-1", "install_reason": "Explicit",
+///       "depends_on": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }],
+///       "optional_deps": [{ "dep_string": "extra", "name": "extra", "depmod": "Any" }] },
+///     { "name": "bash", "version": "5.2-1", "install_reason": "Depend",
+///       "provides": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] },
+///     { "name": "extra", "version": "1.0-1", "install_reason": "Depend" },
+///     { "name": "stale", "version": "1.0-1", "install_reason": "Depend",
+///       "depends_on": [{ "dep_string": "bash", "name": "bash", "depmod": "Any" }] },
+///     { "name": "ping", "version": "1.0-1", "install_reason": "Depend",
+///       "depends_on": [{ "dep_string": "pong", "name": "pong", "depmod": "Any" }] },
+///     { "name": "pong", "version": "1.0-1", "install_reason": "Depend",
+///       "depends_on": [{ "dep_string": "ping", "name": "ping", "depmod": "Any" }] }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+/// let filters = PackageFilters::parse_from(["pacjump", "--orphans"]);
+///
+/// // `bash` is kept by `app` through the provision of `sh`:
+/// let report = OrphanReport::new(&source, &filters);
+/// assert_eq!(report.orphans, ["stale"]);
+/// assert_eq!(report.optional_only.len(), 1);
+/// assert_eq!(report.optional_only[0].name, "extra");
+/// assert_eq!(report.optional_only[0].optional_for, ["app"]);
+/// assert_eq!(report.cycles, [["ping", "pong"]]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+pub struct OrphanReport {
+    /// The packages that no installed package requires, even optionally;
+    /// the same as `pacman -Qdt`.
+    pub orphans: Vec<String>,
+    /// The packages that are only optionally required; together with the
+    /// [`orphans`][OrphanReport::orphans], they are the same as
+    /// `pacman -Qdtt`.
+    pub optional_only: Vec<OptionalOrphan>,
+    /// The groups of packages installed as dependencies, which are required
+    /// only by each other.
+    pub cycles: Vec<Vec<String>>,
+}
+
+impl OrphanReport {
+    /// Finds the orphans among all the packages of the source; the filters
+    /// are expected to select all installed packages, as `--orphans` does.
+    pub fn new<S>(source: &S, pkg_filters: &PackageFilters) -> Self
+    where
+        S: PackageSource + ?Sized,
+    {
+        let graph = DependencyGraph::new(source, pkg_filters);
+        let packages = graph.packages();
+
+        // the installed dependents of each package, by their indices
+        let dependents = |kinds: &[EdgeKind]| -> Vec<BTreeSet<usize>> {
+            (0..graph.len())
+                .map(|idx| {
+                    let edges = graph.dependents(idx, kinds);
+                    edges.map(|x| x.from).filter(|&x| x != idx).collect()
+                })
+                .collect()
+        };
+        let required_by = dependents(&[EdgeKind::Depends]);
+        let optional_for = dependents(&[EdgeKind::OptDepends]);
+
+        let is_dependency = |idx: usize| &*packages[idx].install_reason == "Depend";
+        let names = |indices: &BTreeSet<usize>| -> Vec<String> {
+            let names: BTreeSet<_> = indices.iter().map(|&idx| packages[idx].name).collect();
+            names.into_iter().map(String::from).collect()
+        };
+
+        let mut report = Self::default();
+        for idx in (0..packages.len()).filter(|&idx| is_dependency(idx)) {
+            if !required_by[idx].is_empty() {
+                continue;
+            }
+            let name = packages[idx].name.to_string();
+            match optional_for[idx].is_empty() {
+                true => report.orphans.push(name),
+                false => report.optional_only.push(OptionalOrphan {
+                    name,
+                    optional_for: names(&optional_for[idx]),
+                }),
+            }
+        }
+
+        // a cycle is an orphan if all of its dependents are within itself
+        for component in graph.strongly_connected(&[EdgeKind::Depends, EdgeKind::OptDepends]) {
+            let members: BTreeSet<usize> = component.into_iter().collect();
+            let is_orphan = members.len() > 1
+                && members.iter().all(|&idx| {
+                    is_dependency(idx)
+                        && required_by[idx].is_subset(&members)
+                        && optional_for[idx].is_subset(&members)
+                });
+            if is_orphan {
+                report.cycles.push(names(&members));
+            }
+        }
+
+        report.orphans.sort();
+        report.optional_only.sort_by(|a, b| a.name.cmp(&b.name));
+        report.cycles.sort();
+        report
+    }
+}
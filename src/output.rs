@@ -0,0 +1,515 @@
+//! A module that writes the serialized package information to the output,
+//! in the various formats supported by `pacjump`.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::info::{DepInfo, PackageInfo};
+
+/// Writes each item as a single line of JSON, i.e. in the [JSON Lines]
+/// (NDJSON) format, and flushes the `writer` after every line, so that the
+/// output can be consumed incrementally. Returns the number of lines written.
+///
+/// [JSON Lines]: https://jsonlines.org
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::output::write_json_lines;
+/// let mut buffer = Vec::new();
+/// let count = write_json_lines(&mut buffer, [["a", "b"], ["c", "d"]])?;
+/// assert_eq!(count, 2);
+/// assert_eq!(String::from_utf8(buffer)?, "[\"a\",\"b\"]\n[\"c\",\"d\"]\n");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn write_json_lines<W, I>(writer: &mut W, items: I) -> anyhow::Result<usize>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut count = 0;
+    for item in items {
+        serde_json::to_writer(&mut *writer, &item)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// The output formats of the package information.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// A single JSON array
+    #[default]
+    Json,
+    /// JSON Lines, one package per line
+    Ndjson,
+    /// Comma separated values, one package per row
+    Csv,
+    /// Tab separated values, one package per row
+    Tsv,
+    /// Graphviz DOT of the `--recurse` dependency graph
+    Dot,
+    /// A nested tree of the `--recurse` dependencies, like `pactree`
+    Tree,
+    /// CycloneDX software bill of materials, in JSON
+    Cyclonedx,
+    /// SPDX 2.3 software bill of materials, in the tag-value format
+    Spdx,
+    /// SPDX 2.3 software bill of materials, in JSON
+    SpdxJson,
+}
+
+/// Options of the tabular output, i.e. CSV or TSV.
+#[derive(Debug, Clone)]
+pub struct TableOptions {
+    /// The separator between the columns, e.g. `,` or `\t`.
+    pub delimiter: char,
+    /// The paths of the fields to be used as columns, in order, e.g.
+    /// `name`, `sync_with.version`; the path is mapped over lists, e.g.
+    /// `depends_on.name`. When empty, all the top-level fields are used,
+    /// except for the nested records in [`NESTED_FIELDS`].
+    pub columns: Vec<String>,
+    /// The separator to join the items of lists in a single cell.
+    pub list_separator: String,
+}
+
+/// The fields of [`PackageInfo`] that contain nested records and are thus
+/// excluded from the default columns.
+pub const NESTED_FIELDS: &[&str] = &["sync_with"];
+
+/// Writes each item as a row of a table, with a header row of the column
+/// names; the scalar fields are written as they are, and the lists are
+/// joined by the [`list_separator`][TableOptions::list_separator].
+/// Dependencies are written as their `dep_string`. Returns the number of
+/// rows written, excluding the header.
+///
+/// For CSV, cells are quoted when necessary, as in [RFC 4180]; for TSV, tabs
+/// and newlines in cells are replaced by spaces.
+///
+/// [RFC 4180]: https://www.rfc-editor.org/rfc/rfc4180
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::output::{write_table, TableOptions};
+/// # use serde_json::json;
+/// let packages = [
+///     json!({ "name": "foo", "licenses": ["MIT", "BSD"], "depends_on": [
+///         { "dep_string": "bar>=1.0", "name": "bar" },
+///         { "dep_string": "baz", "name": "baz" },
+///     ]}),
+///     json!({ "name": "foo, the sequel", "licenses": [], "depends_on": [] }),
+/// ];
+/// let options = TableOptions {
+///     delimiter: ',',
+///     columns: vec![],
+///     list_separator: " ".into(),
+/// };
+///
+/// let mut buffer = Vec::new();
+/// write_table(&mut buffer, &packages, &options)?;
+/// assert_eq!(String::from_utf8(buffer)?, [
+///     "name,licenses,depends_on\n",
+///     "foo,MIT BSD,bar>=1.0 baz\n",
+///     "\"foo, the sequel\",,\n",
+/// ].concat());
+///
+/// let options = TableOptions {
+///     delimiter: '\t',
+///     columns: vec!["depends_on.name".into(), "name".into()],
+///     list_separator: ";".into(),
+/// };
+/// let mut buffer = Vec::new();
+/// write_table(&mut buffer, &packages[..1], &options)?;
+/// assert_eq!(String::from_utf8(buffer)?, "depends_on.name\tname\nbar;baz\tfoo\n");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn write_table<W, I>(writer: &mut W, items: I, options: &TableOptions) -> anyhow::Result<usize>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    let mut columns: Option<Vec<Vec<String>>> = match options.columns.is_empty() {
+        true => None,
+        false => Some(
+            options
+                .columns
+                .iter()
+                .map(|column| column.split('.').map(String::from).collect())
+                .collect(),
+        ),
+    };
+
+    let mut count = 0;
+    for item in items {
+        let value = serde_json::to_value(item)?;
+        let columns = match &columns {
+            Some(columns) => columns,
+            None => {
+                let keys = match &value {
+                    serde_json::Value::Object(map) => map
+                        .keys()
+                        .filter(|key| !NESTED_FIELDS.contains(&key.as_str()))
+                        .map(|key| vec![key.clone()])
+                        .collect(),
+                    _ => vec![vec![]],
+                };
+                columns.insert(keys)
+            }
+        };
+        if count == 0 {
+            let header = columns.iter().map(|path| path.join("."));
+            write_row(writer, header, options.delimiter)?;
+        }
+        let row = columns.iter().map(|path| {
+            let mut leaves = Vec::new();
+            lookup(&value, path, &mut leaves);
+            leaves
+                .into_iter()
+                .filter_map(format_cell)
+                .collect::<Vec<_>>()
+                .join(&options.list_separator)
+        });
+        write_row(writer, row, options.delimiter)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Collects the values at a field path, mapping over lists.
+fn lookup<'v>(
+    value: &'v serde_json::Value,
+    path: &[String],
+    leaves: &mut Vec<&'v serde_json::Value>,
+) {
+    use serde_json::Value;
+    match (value, path.split_first()) {
+        (Value::Array(items), _) => {
+            for item in items {
+                lookup(item, path, leaves)
+            }
+        }
+        (_, None) => leaves.push(value),
+        (Value::Object(map), Some((key, rest))) => {
+            if let Some(value) = map.get(key) {
+                lookup(value, rest, leaves)
+            }
+        }
+        (_, Some(_)) => {}
+    }
+}
+
+/// Formats a single value in a cell; nulls are omitted.
+fn format_cell(value: &serde_json::Value) -> Option<String> {
+    use serde_json::Value;
+    match value {
+        Value::Null => None,
+        Value::String(string) => Some(string.clone()),
+        Value::Object(map) => match map.get("dep_string") {
+            Some(Value::String(dep_string)) => Some(dep_string.clone()),
+            _ => Some(value.to_string()),
+        },
+        value => Some(value.to_string()),
+    }
+}
+
+/// Writes a row of cells, escaped for the `delimiter`.
+fn write_row<W, I>(writer: &mut W, cells: I, delimiter: char) -> anyhow::Result<()>
+where
+    W: Write,
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    let cells: Vec<_> = cells
+        .into_iter()
+        .map(|cell| {
+            let cell = cell.as_ref();
+            match delimiter {
+                '\t' => cell.replace(['\t', '\n', '\r'], " "),
+                _ if cell.contains([delimiter, '"', '\n', '\r']) => {
+                    format!("\"{}\"", cell.replace('"', "\"\""))
+                }
+                _ => cell.to_string(),
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", cells.join(&delimiter.to_string()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes the dependency graph of the packages in the Graphviz DOT format.
+/// The packages are rendered as nodes, labelled with their versions and
+/// installed sizes, and the resolved dependencies, i.e. those with a
+/// [`satisfier`][DepInfo::satisfier], are rendered as edges: solid for
+/// [`depends_on`][PackageInfo::depends_on], dashed for
+/// [`optional_deps`][PackageInfo::optional_deps], and dotted for
+/// [`makedepends`][PackageInfo::makedepends] and
+/// [`checkdepends`][PackageInfo::checkdepends]. An edge is labelled with
+/// the dependency string when it is satisfied by another package, e.g.
+/// through `provides`.
+///
+/// This is intended for the closure of [`recurse_dependencies`], where the
+/// satisfiers are resolved. Returns the number of nodes written.
+///
+/// [`recurse_dependencies`]: crate::recurse_deps::recurse_dependencies
+///
+pub fn write_dot<'a, W, I>(writer: &mut W, packages: I) -> anyhow::Result<usize>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a PackageInfo<'a>>,
+{
+    writeln!(writer, "digraph pacjump {{")?;
+    writeln!(writer, "  rankdir=LR;")?;
+    writeln!(writer, "  node [shape=box];")?;
+
+    let mut count = 0;
+    for pkg in packages {
+        let id = format!("{}={}", pkg.name, pkg.version);
+        let label = format!(
+            "{}\n{}\n{}",
+            pkg.name,
+            pkg.version,
+            format_size(pkg.installed_size)
+        );
+        writeln!(writer, "  {} [label={}];", quote(&id), quote(&label))?;
+
+        for (style, deps) in [
+            ("solid", &pkg.depends_on),
+            ("dashed", &pkg.optional_deps),
+            ("dotted", &pkg.makedepends),
+            ("dotted", &pkg.checkdepends),
+        ] {
+            let edges = deps.iter().filter_map(|dep: &DepInfo| {
+                dep.satisfier.as_ref().map(|satisfier| (dep, satisfier))
+            });
+            for (dep, satisfier) in edges {
+                let mut attrs = vec![format!("style={style}")];
+                if !satisfier.starts_with(&format!("{}=", dep.dep_string)) {
+                    attrs.push(format!("label={}", quote(&dep.dep_string)));
+                }
+                writeln!(
+                    writer,
+                    "  {} -> {} [{}];",
+                    quote(&id),
+                    quote(satisfier),
+                    attrs.join(", ")
+                )?;
+            }
+        }
+        count += 1;
+    }
+
+    writeln!(writer, "}}")?;
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Writes the dependency tree of the packages, like `pactree`, with the
+/// first package as the root. The children of a package are its resolved
+/// dependencies, i.e. those with a [`satisfier`][DepInfo::satisfier] among
+/// the packages, labelled e.g. `bash provides sh` when satisfied through
+/// `provides`, and marked as `(optional)`, `(make)` or `(check)` unless
+/// they are in [`depends_on`][PackageInfo::depends_on].
+///
+/// The dependencies of a package are only expanded once; the package is
+/// marked with `(*)` where it appears again, as `cargo tree` does. The
+/// tree is truncated below `max_depth`, if given. Returns the number of
+/// lines written.
+///
+/// This is intended for the closure of [`recurse_dependencies`], where the
+/// satisfiers are resolved.
+///
+/// [`recurse_dependencies`]: crate::recurse_deps::recurse_dependencies
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::info::{OwnedPackageInfo, PackageInfo};
+/// # use pacjump::output::write_tree;
+/// let packages: Vec<OwnedPackageInfo> = serde_json::from_str(r#"[
+///     { "name": "app", "version": "1", "depends_on": [
+///         { "dep_string": "sh", "name": "sh", "depmod": "Any", "satisfier": "bash=5" },
+///         { "dep_string": "readline", "name": "readline", "depmod": "Any", "satisfier": "readline=8" }
+///     ] },
+///     { "name": "bash", "version": "5", "depends_on": [
+///         { "dep_string": "readline", "name": "readline", "depmod": "Any", "satisfier": "readline=8" }
+///     ] },
+///     { "name": "readline", "version": "8", "depends_on": [
+///         { "dep_string": "glibc", "name": "glibc", "depmod": "Any", "satisfier": "glibc=2" }
+///     ] },
+///     { "name": "glibc", "version": "2" }
+/// ]"#)?;
+/// let packages: Vec<PackageInfo> = packages.iter().map(PackageInfo::from).collect();
+///
+/// // `readline` is only expanded where it first appears:
+/// let mut buffer = Vec::new();
+/// assert_eq!(write_tree(&mut buffer, &packages, None)?, 5);
+/// assert_eq!(
+///     String::from_utf8(buffer)?,
+///     "app\n├─bash provides sh\n│ └─readline\n│   └─glibc\n└─readline (*)\n"
+/// );
+///
+/// let mut buffer = Vec::new();
+/// assert_eq!(write_tree(&mut buffer, &packages, Some(1))?, 3);
+/// assert_eq!(String::from_utf8(buffer)?, "app\n├─bash provides sh\n└─readline\n");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn write_tree<'a, W, I>(
+    writer: &mut W,
+    packages: I,
+    max_depth: Option<u64>,
+) -> anyhow::Result<usize>
+where
+    W: Write,
+    I: IntoIterator<Item = &'a PackageInfo<'a>>,
+{
+    let packages: Vec<_> = packages.into_iter().collect();
+    let Some(root) = packages.first() else {
+        return Ok(0);
+    };
+    let index: HashMap<String, usize> = packages
+        .iter()
+        .enumerate()
+        .map(|(idx, pkg)| (format!("{}={}", pkg.name, pkg.version), idx))
+        .collect();
+
+    // the resolved dependencies of a package, with their labels
+    let children = |pkg: &PackageInfo| -> Vec<(usize, String)> {
+        let (index, packages) = (&index, &packages);
+        [
+            ("", &pkg.depends_on),
+            (" (optional)", &pkg.optional_deps),
+            (" (make)", &pkg.makedepends),
+            (" (check)", &pkg.checkdepends),
+        ]
+        .into_iter()
+        .flat_map(|(kind, deps)| {
+            deps.iter().filter_map(move |dep: &DepInfo| {
+                let &idx = index.get(dep.satisfier.as_deref()?)?;
+                let name = packages[idx].name;
+                let label = match dep.name == name {
+                    true => format!("{name}{kind}"),
+                    false => format!("{name} provides {}{kind}", dep.dep_string),
+                };
+                Some((idx, label))
+            })
+        })
+        .collect()
+    };
+
+    // the nodes to be written, depth first, with the prefixes of their
+    // lines and of their children's lines
+    let mut stack = vec![(0, root.name.to_string(), String::new(), String::new(), 0)];
+    let mut expanded = HashSet::new();
+    let mut count = 0;
+    while let Some((idx, label, prefix, child_prefix, depth)) = stack.pop() {
+        let children = children(packages[idx]);
+        let truncated = max_depth.is_some_and(|max_depth| depth >= max_depth);
+        let repeated = !truncated && !children.is_empty() && !expanded.insert(idx);
+        let mark = if repeated { " (*)" } else { "" };
+        writeln!(writer, "{prefix}{label}{mark}")?;
+        count += 1;
+        if truncated || repeated {
+            continue;
+        }
+        let last = children.len().saturating_sub(1);
+        for (n, (child, label)) in children.into_iter().enumerate().rev() {
+            let (branch, indent) = match n == last {
+                true => ("└─", "  "),
+                false => ("├─", "│ "),
+            };
+            stack.push((
+                child,
+                label,
+                format!("{child_prefix}{branch}"),
+                format!("{child_prefix}{indent}"),
+                depth + 1,
+            ));
+        }
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Quotes a string as a DOT identifier; `\n` line breaks are kept.
+fn quote(string: &str) -> String {
+    format!("\"{}\"", string.replace('"', "\\\""))
+}
+
+/// Formats a size in bytes with binary prefixes, like `numfmt --to=iec`.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::output::format_size;
+/// assert_eq!(format_size(42), "42 B");
+/// assert_eq!(format_size(1536), "1.5 KiB");
+/// assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+/// ```
+///
+pub fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes.abs() < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size.abs() >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// Formats a [`SystemTime`] as an RFC 3339 timestamp in UTC, e.g.
+/// `2024-08-01T12:34:56Z`.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::output::format_timestamp;
+/// # use std::time::{Duration, UNIX_EPOCH};
+/// assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+/// let time = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+/// assert_eq!(format_timestamp(time), "2024-02-29T23:59:59Z");
+/// ```
+///
+pub fn format_timestamp(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // civil date from days since the epoch, see:
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
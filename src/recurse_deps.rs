@@ -1,87 +1,346 @@
-use alpm::{Alpm, AlpmListMut, Db};
 use indexmap::IndexSet;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
-use crate::info::{DepInfo, PacList, PackageInfo};
-use crate::reverse_deps::ReverseDepsDatabase;
+use crate::info::{ClosureInfo, ClosureParent, DepInfo, PacList, PackageInfo};
+use crate::source::PackageSource;
 use crate::PackageFilters;
 
+/// A field of [`PackageInfo`] listing the dependencies to be resolved.
+#[derive(Clone, Copy, PartialEq)]
+enum DepField {
+    MakeDepends,
+    CheckDepends,
+    DependsOn,
+    OptionalDeps,
+}
+
+impl DepField {
+    fn of<'a, 'b>(self, pkg_info: &'b mut PackageInfo<'a>) -> &'b mut PacList<DepInfo<'a>> {
+        match self {
+            Self::MakeDepends => &mut pkg_info.makedepends,
+            Self::CheckDepends => &mut pkg_info.checkdepends,
+            Self::DependsOn => &mut pkg_info.depends_on,
+            Self::OptionalDeps => &mut pkg_info.optional_deps,
+        }
+    }
+}
+
+/// A package being recursed, with its dependencies yet to be resolved.
+struct Frame<'a> {
+    pkg_info: PackageInfo<'a>,
+    depth: u64,
+    fields: Vec<DepField>,
+    /// the pending dependencies, in the reverse order of resolution
+    pending: Vec<(DepField, DepInfo<'a>)>,
+    resolved: Vec<(DepField, DepInfo<'a>)>,
+}
+
+impl<'a> Frame<'a> {
+    /// Enters a package, reached through its `first_parent`, and records its
+    /// `name=version` into the `deps_set`.
+    fn new(
+        pkg_filters: &PackageFilters,
+        mut pkg_info: PackageInfo<'a>,
+        depth: u64,
+        first_parent: Option<ClosureParent>,
+        deps_set: &mut IndexSet<String>,
+    ) -> Self {
+        eprintln!(
+            "# level {}: recursing into '{}': {:?}\n",
+            depth, pkg_info.name, pkg_info.depends_on
+        );
+        deps_set.insert(format!("{}={}", pkg_info.name, pkg_info.version));
+        let mut fields = Vec::new();
+        if pkg_filters.build && depth == 0 {
+            fields.extend([DepField::MakeDepends, DepField::CheckDepends]);
+        }
+        fields.push(DepField::DependsOn);
+        if pkg_filters.optional {
+            fields.push(DepField::OptionalDeps);
+        }
+        let mut pending = Vec::new();
+        for &field in &fields {
+            let deps = std::mem::replace(field.of(&mut pkg_info), Vec::new().into());
+            pending.extend(deps.into_iter().map(|dep| (field, dep)));
+        }
+        pending.reverse();
+        pkg_info.closure = Some(ClosureInfo {
+            depth,
+            first_parent,
+            parents: Vec::new(),
+        });
+        Self {
+            pkg_info,
+            depth,
+            fields,
+            pending,
+            resolved: Vec::new(),
+        }
+    }
+
+    /// Leaves the package, with its dependencies replaced by the resolved
+    /// ones.
+    fn finish(self) -> PackageInfo<'a> {
+        let mut pkg_info = self.pkg_info;
+        // the dependencies are resolved field by field
+        let mut resolved = self.resolved.into_iter().peekable();
+        for field in self.fields {
+            let deps: Vec<_> = std::iter::from_fn(|| resolved.next_if(|(x, _)| *x == field))
+                .map(|(_, dep)| dep)
+                .collect();
+            *field.of(&mut pkg_info) = deps.into();
+        }
+        pkg_info
+    }
+}
+
 /// Recurses the dependency tree of a [`PackageInfo`], finds the packages
-/// satisfying the dependency requirements, collects the satisfiers' data
-/// into a mutable [`IndexSet`], and adds the satisfiers' [`PackageInfo`]s
-/// into a mutable [`Vec`].
-pub fn recurse_dependencies<'a, T>(
-    handle: &'a Alpm,
-    databases: T,
+/// satisfying the dependency requirements from a [`PackageSource`], collects
+/// the satisfiers' data into a mutable [`IndexSet`], and adds the
+/// satisfiers' [`PackageInfo`]s into a mutable [`Vec`]. With `--build`, the
+/// `makedepends` and `checkdepends` are followed at the top level, i.e. at
+/// `depth` 0.
+///
+/// The [`closure`][PackageInfo::closure] of each [`PackageInfo`] is set, with
+/// the depth at which it is first reached, and the parents pulling it in,
+/// along with the dependency strings.
+///
+/// The tree is walked depth first, with an explicit stack rather than the
+/// call stack, so that huge closures are safe on the default thread stacks.
+/// The satisfiers are collected in the order of discovery, and the
+/// [`PackageInfo`]s are added once all of their dependencies are done.
+pub fn recurse_dependencies<'a, S>(
+    source: &'a S,
     pkg_filters: &PackageFilters,
-    reverse_deps: &'a ReverseDepsDatabase,
     pkg_info: PackageInfo<'a>,
     depth: u64,
     deps_set: &mut IndexSet<String>,
     deps_pkgs: &mut Vec<PackageInfo<'a>>,
-) -> ()
+) where
+    S: PackageSource + ?Sized,
+{
+    let mut stack = vec![Frame::new(pkg_filters, pkg_info, depth, None, deps_set)];
+    // the parents of the packages, by their `name=version`
+    let mut parents: HashMap<String, Vec<ClosureParent>> = HashMap::new();
+    while let Some(frame) = stack.last_mut() {
+        let Some((field, dep)) = frame.pending.pop() else {
+            let frame = stack.pop().unwrap();
+            // kept even for `--summary`, e.g. to sum up the sizes
+            deps_pkgs.push(frame.finish());
+            continue;
+        };
+        let next_depth = frame.depth + 1;
+        let Some(pkg_info) = source.find_satisfier(pkg_filters, &dep) else {
+            frame.resolved.push((field, dep));
+            continue;
+        };
+        let satisfier = format!("{}={}", pkg_info.name, pkg_info.version);
+        let parent = ClosureParent {
+            name: frame.pkg_info.name.to_string(),
+            dep_string: dep.dep_string.clone(),
+        };
+        parents
+            .entry(satisfier.clone())
+            .or_default()
+            .push(parent.clone());
+        let duplicated = deps_set.contains(&satisfier);
+        if duplicated {
+            eprintln!(
+                "# level {}: duplicated dependency: '{}' provides '{}'",
+                next_depth, satisfier, dep.dep_string
+            );
+        }
+        frame.resolved.push((
+            field,
+            DepInfo {
+                satisfier: Some(satisfier),
+                ..dep
+            },
+        ));
+        if !duplicated {
+            let frame = Frame::new(pkg_filters, pkg_info, next_depth, Some(parent), deps_set);
+            stack.push(frame);
+        }
+    }
+    for pkg_info in deps_pkgs {
+        let id = format!("{}={}", pkg_info.name, pkg_info.version);
+        if let (Some(closure), Some(parents)) = (&mut pkg_info.closure, parents.remove(&id)) {
+            closure.parents = parents;
+        }
+    }
+}
+
+/// Recursively collects the dependencies of the package `name` from a
+/// [`PackageSource`]. Returns the `name=version` of all packages in the
+/// closure, in the order of discovery, and their [`PackageInfo`]s, with the
+/// dependencies preceding their dependents.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::recurse_deps::recurse_closure;
+/// # use pacjump::source::DumpSource;
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "foo", "version": "1",
+///       "depends_on": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" },
+///                      { "dep_string": "glibc", "name": "glibc", "depmod": "Any" }] },
+///     { "name": "bash", "version": "5",
+///       "depends_on": [{ "dep_string": "glibc", "name": "glibc", "depmod": "Any" }],
+///       "provides": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] },
+///     { "name": "glibc", "version": "2" }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+/// let filters = PackageFilters::parse_from(["pacjump", "--recurse=foo"]);
+///
+/// let (deps_set, deps_pkgs) = recurse_closure(&source, &filters, "foo")?;
+/// assert_eq!(deps_set, ["foo=1", "bash=5", "glibc=2"].map(String::from).into());
+///
+/// // `glibc` is first reached through `bash`, and required by `foo` as well:
+/// let glibc = deps_pkgs.iter().find(|pkg| pkg.name == "glibc").unwrap();
+/// let closure = glibc.closure.as_ref().unwrap();
+/// assert_eq!(closure.depth, 2);
+/// assert_eq!(closure.first_parent.as_ref().unwrap().name, "bash");
+/// let parents: Vec<_> = closure.parents.iter().map(|x| x.name.as_str()).collect();
+/// assert_eq!(parents, ["bash", "foo"]);
+///
+/// let bash = deps_pkgs.iter().find(|pkg| pkg.name == "bash").unwrap();
+/// assert_eq!(bash.closure.as_ref().unwrap().parents[0].dep_string, "sh");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn recurse_closure<'a, S>(
+    source: &'a S,
+    pkg_filters: &PackageFilters,
+    name: &str,
+) -> anyhow::Result<(IndexSet<String>, Vec<PackageInfo<'a>>)>
 where
-    T: IntoIterator<Item = &'a Db> + Clone,
+    S: PackageSource + ?Sized,
 {
-    eprintln!(
-        "# level {}: recursing into '{}': {:?}\n",
-        depth, pkg_info.name, pkg_info.depends_on
+    let pkg_info = source.find_package(pkg_filters, name)?;
+    let mut deps_set = IndexSet::new();
+    let mut deps_pkgs = Vec::new();
+    recurse_dependencies(
+        source,
+        pkg_filters,
+        pkg_info,
+        0,
+        &mut deps_set,
+        &mut deps_pkgs,
     );
-    deps_set.insert(format!("{}={}", pkg_info.name, pkg_info.version));
-    let mut_list = AlpmListMut::from_iter(databases.clone().into_iter());
-    let db_list = mut_list.list();
-    let mut satisfied_dependencies = |dependencies: PacList<DepInfo<'a>>| -> Vec<DepInfo<'a>> {
-        dependencies
-            .into_iter()
-            .map(|dep| {
-                let pkg = match db_list.clone().find_satisfier(dep.dep_string.clone()) {
-                    Some(pkg) => pkg,
-                    None => return dep.clone(),
-                };
-                let satisfier = format!("{}={}", pkg.name(), pkg.version());
-                let next_depth = depth + 1;
-                if !deps_set.contains(&satisfier) {
-                    let pkg_info = pkg_filters
-                        .generate_pkg_info(handle, pkg, &reverse_deps)
-                        .unwrap_or(pkg.into());
-                    recurse_dependencies(
-                        &handle,
-                        databases.clone(),
-                        pkg_filters,
-                        &reverse_deps,
-                        pkg_info,
-                        next_depth,
-                        deps_set,
-                        deps_pkgs,
-                    );
-                } else {
-                    eprintln!(
-                        "# level {}: duplicated dependency: '{}' provides '{}'",
-                        next_depth,
-                        satisfier,
-                        dep.dep_string.clone()
-                    );
-                }
-                DepInfo {
-                    satisfier: Some(satisfier),
-                    ..dep.clone()
-                }
-            })
-            .collect()
+    deps_pkgs.reverse();
+    Ok((deps_set, deps_pkgs))
+}
+
+/// Collects the packages that depend on the package `name`, transitively,
+/// from the [`ReverseDepsDatabase`] of a [`PackageSource`]; the packages
+/// that optionally depend on it are followed as well with `--optional`. A
+/// dependent is kept only if it is found in the source, i.e. it is installed
+/// unless `--sync`, and if its own dependencies, as they are found, still
+/// name the package. The packages are returned in the order of discovery,
+/// i.e. by their [`depth`][ClosureInfo::depth], starting from `name` itself.
+///
+/// [`ReverseDepsDatabase`]: crate::reverse_deps::ReverseDepsDatabase
+///
+/// Note that the reverse dependencies are looked up by the names of the
+/// package and its provisions, regardless of the required versions.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::recurse_deps::reverse_closure;
+/// # use pacjump::source::DumpSource;
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "glibc", "version": "2.40-1" },
+///     { "name": "bash", "version": "5.2-1",
+///       "depends_on": [{ "dep_string": "glibc", "name": "glibc", "depmod": "Any" }],
+///       "provides": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] },
+///     { "name": "foo", "version": "1.0-1",
+///       "depends_on": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] },
+///     { "name": "bar", "version": "1.0-1",
+///       "optional_deps": [{ "dep_string": "foo", "name": "foo", "depmod": "Any" }] },
+///     { "name": "aur-tool", "version": "1.0-1", "repository": "local", "sync_status": "foreign",
+///       "depends_on": [{ "dep_string": "bash", "name": "bash", "depmod": "Any" }] }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+///
+/// // the foreign `aur-tool` is a dependent as well:
+/// let filters = PackageFilters::parse_from(["pacjump", "--reverse-recurse=glibc"]);
+/// let closure = reverse_closure(&source, &filters, "glibc")?;
+/// let names: Vec<_> = closure.iter().map(|pkg| pkg.name).collect();
+/// assert_eq!(names, ["glibc", "bash", "aur-tool", "foo"]);
+/// let depths: Vec<_> = closure.iter().map(|pkg| pkg.closure.as_ref().unwrap().depth).collect();
+/// assert_eq!(depths, [0, 1, 2, 2]);
+///
+/// let filters = PackageFilters::parse_from(["pacjump", "--reverse-recurse=glibc", "--optional"]);
+/// let closure = reverse_closure(&source, &filters, "glibc")?;
+/// assert_eq!(closure.last().unwrap().name, "bar");
+///
+/// assert!(reverse_closure(&source, &filters, "musl").is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn reverse_closure<'a, S>(
+    source: &'a S,
+    pkg_filters: &PackageFilters,
+    name: &str,
+) -> anyhow::Result<Vec<PackageInfo<'a>>>
+where
+    S: PackageSource + ?Sized,
+{
+    let reverse_deps = source.reverse_deps();
+    let mut maps = vec![&reverse_deps.required_by];
+    if pkg_filters.optional {
+        maps.push(&reverse_deps.optional_for);
+    }
+    // whether a dependent, as it is found, depends on any of the names
+    let depends_on_any = |pkg_info: &PackageInfo, names: &BTreeSet<&str>| {
+        let mut deps = pkg_info.depends_on.iter().collect::<Vec<_>>();
+        if pkg_filters.optional {
+            deps.extend(pkg_info.optional_deps.iter());
+        }
+        deps.into_iter().any(|dep| names.contains(dep.name))
     };
-    let pkg_info = match pkg_filters.optional {
-        true => PackageInfo {
-            depends_on: satisfied_dependencies(pkg_info.depends_on).into(),
-            optional_deps: satisfied_dependencies(pkg_info.optional_deps).into(),
-            ..pkg_info
-        },
-        false => PackageInfo {
-            depends_on: satisfied_dependencies(pkg_info.depends_on).into(),
+
+    let root = source.find_package(pkg_filters, name)?;
+    let mut visited = HashSet::from([root.name.to_string()]);
+    let mut queue = VecDeque::from([(root, 0)]);
+    let mut closure = Vec::new();
+    while let Some((pkg_info, depth)) = queue.pop_front() {
+        eprintln!("# level {}: dependents of '{}'", depth, pkg_info.name);
+        let names: BTreeSet<&str> = std::iter::once(pkg_info.name)
+            .chain(pkg_info.provides.iter().map(|x| x.name))
+            .collect();
+        let dependents: BTreeSet<&str> = names
+            .iter()
+            .flat_map(|&name| maps.iter().filter_map(move |map| map.get(name)))
+            .flat_map(|reverse_deps| reverse_deps.iter().map(String::as_str))
+            .collect();
+        for dependent in dependents {
+            if visited.contains(dependent) {
+                continue;
+            }
+            // the reverse dependencies may be recorded from another version
+            // of the dependent, e.g. in the sync databases
+            match source.find_package(pkg_filters, dependent) {
+                Ok(dependent_info) if depends_on_any(&dependent_info, &names) => {
+                    visited.insert(dependent.to_string());
+                    queue.push_back((dependent_info, depth + 1))
+                }
+                Ok(_) => continue,
+                Err(msg) => eprintln!("# level {}: {msg}", depth + 1),
+            }
+        }
+        closure.push(PackageInfo {
+            closure: Some(ClosureInfo {
+                depth,
+                ..Default::default()
+            }),
             ..pkg_info
-        },
-    };
-    if pkg_filters.summary {
-        return;
-    } else {
-        deps_pkgs.push(pkg_info);
+        });
     }
+    Ok(closure)
 }
@@ -1,5 +1,5 @@
 //! A module that generates different kinds of reverse dependencies from pacman
-//! sync databases and gathers them in a big [HashMap] from package names to
+//! databases and gathers them in a big [HashMap] from package names to
 //! their respective [BTreeSet]s of reverse dependencies. The key ingredient,
 //! [`get_reverse_deps_map`], is stolen from <https://github.com/jelly/pacquery>.
 //!
@@ -18,8 +18,20 @@
 use alpm::{Alpm, AlpmList, Dep, Package};
 use std::collections::{BTreeSet, HashMap};
 
+use crate::info::{DepInfo, PacList, PackageInfo};
+
 /// A wrapper of [`BTreeSet`] for reverse dependencies.
-#[derive(Debug, derive_more::Deref, derive_more::DerefMut, serde::Serialize)]
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    derive_more::Deref,
+    derive_more::DerefMut,
+    serde::Serialize,
+    serde::Deserialize,
+    schemars::JsonSchema,
+)]
 pub struct ReverseDeps(BTreeSet<String>);
 impl ReverseDeps {
     /// A constant reference to an empty set of reverse dependencies.
@@ -40,9 +52,12 @@ impl Default for &ReverseDeps {
 /// packages that are dependent on it.
 pub type ReverseDepsMap = HashMap<String, ReverseDeps>;
 
-/// Generates a [HashMap] of all reverse dependencies from the sync database
-/// [`Alpm::syncdbs`]. The type of dependencies are specified by the
-/// `get_dependencies` argument. See [`ReverseDepsDatabase`] for its usage.
+/// Generates a [HashMap] of all reverse dependencies from the sync databases
+/// [`Alpm::syncdbs`] and the local database [`Alpm::localdb`], so that the
+/// installed packages missing from the sync databases, e.g. those from the
+/// AUR, are recorded as dependents as well. The type of dependencies are
+/// specified by the `get_dependencies` argument. See [`ReverseDepsDatabase`]
+/// for its usage.
 ///
 /// This function is ported from: <https://github.com/jelly/pacquery>.
 ///
@@ -51,21 +66,12 @@ pub fn get_reverse_deps_map(
     get_dependencies: fn(&Package) -> AlpmList<&Dep>,
 ) -> ReverseDepsMap {
     let mut reverse_deps: ReverseDepsMap = HashMap::new();
-    let dbs = handle.syncdbs();
+    let dbs = handle.syncdbs().iter().chain([handle.localdb()]);
 
     for db in dbs {
         for pkg in db.pkgs() {
             for dep in get_dependencies(pkg) {
-                reverse_deps
-                    .entry(dep.name().to_string())
-                    .and_modify(|e| {
-                        e.insert(pkg.name().to_string());
-                    })
-                    .or_insert_with(|| {
-                        let mut modify = ReverseDeps::new();
-                        modify.insert(pkg.name().to_string());
-                        modify
-                    });
+                insert_reverse_dep(&mut reverse_deps, dep.name(), pkg.name());
             }
         }
     }
@@ -73,6 +79,20 @@ pub fn get_reverse_deps_map(
     reverse_deps
 }
 
+/// Records that the package `pkg_name` is dependent on `dep_name`.
+fn insert_reverse_dep(reverse_deps: &mut ReverseDepsMap, dep_name: &str, pkg_name: &str) {
+    reverse_deps
+        .entry(dep_name.to_string())
+        .and_modify(|e| {
+            e.insert(pkg_name.to_string());
+        })
+        .or_insert_with(|| {
+            let mut modify = ReverseDeps::new();
+            modify.insert(pkg_name.to_string());
+            modify
+        });
+}
+
 /// A collection of all different kinds of reverse dependencies maps.
 pub struct ReverseDepsDatabase {
     pub optional_for: ReverseDepsMap,
@@ -95,3 +115,32 @@ impl From<&Alpm> for ReverseDepsDatabase {
         }
     }
 }
+
+impl ReverseDepsDatabase {
+    /// Generates the reverse dependencies maps from the dependencies of the
+    /// given packages, e.g. those loaded from a dump. Only the packages in
+    /// the list are considered as dependents.
+    pub fn from_packages<'a, I>(packages: I) -> Self
+    where
+        I: IntoIterator<Item = PackageInfo<'a>>,
+    {
+        let mut database = Self {
+            optional_for: HashMap::new(),
+            required_by: HashMap::new(),
+            required_by_make: HashMap::new(),
+            required_by_check: HashMap::new(),
+        };
+        let insert = |map: &mut ReverseDepsMap, deps: &PacList<DepInfo>, name: &str| {
+            for dep in deps {
+                insert_reverse_dep(map, dep.name, name);
+            }
+        };
+        for pkg in packages {
+            insert(&mut database.optional_for, &pkg.optional_deps, pkg.name);
+            insert(&mut database.required_by, &pkg.depends_on, pkg.name);
+            insert(&mut database.required_by_make, &pkg.makedepends, pkg.name);
+            insert(&mut database.required_by_check, &pkg.checkdepends, pkg.name);
+        }
+        database
+    }
+}
@@ -0,0 +1,594 @@
+//! A module that generates software bills of materials (SBOMs) from the
+//! package information, in the [CycloneDX] and the [SPDX] formats.
+//!
+//! Each package is identified by its [purl], e.g.
+//! `pkg:alpm/arch/bash@5.2.026-2?arch=x86_64`, and the dependencies between
+//! the packages are encoded as the dependency graph of the SBOM.
+//!
+//! [CycloneDX]: https://cyclonedx.org/docs/1.5/json/
+//! [SPDX]: https://spdx.github.io/spdx-spec/v2.3/
+//! [purl]: https://github.com/package-url/purl-spec
+//!
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+
+use crate::graph::{DependencyGraph, EdgeKind};
+use crate::output::format_timestamp;
+
+/// The CycloneDX specification version of the generated SBOM.
+pub const CYCLONEDX_SPEC_VERSION: &str = "1.5";
+
+/// Returns the package url of a package, with the `arch` qualifier.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::sbom::purl;
+/// assert_eq!(purl("bash", "5.2.026-2", Some("x86_64")), "pkg:alpm/arch/bash@5.2.026-2?arch=x86_64");
+/// assert_eq!(purl("gtk+", "1:2.0-1", None), "pkg:alpm/arch/gtk%2B@1%3A2.0-1");
+/// ```
+///
+pub fn purl(name: &str, version: &str, arch: Option<&str>) -> String {
+    let purl = format!(
+        "pkg:alpm/arch/{}@{}",
+        percent_encode(name),
+        percent_encode(version)
+    );
+    match arch {
+        Some(arch) => format!("{purl}?arch={}", percent_encode(arch)),
+        None => purl,
+    }
+}
+
+/// Percent-encodes all characters except the unreserved ones.
+fn percent_encode(string: &str) -> String {
+    string
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Returns the dependency graph of the SBOM as a list of `(package,
+/// dependencies)` indices, from the [`Depends`][EdgeKind::Depends] edges of
+/// a [`DependencyGraph`].
+fn depends_on(graph: &DependencyGraph) -> Vec<(usize, Vec<usize>)> {
+    (0..graph.len())
+        .map(|index| {
+            let mut deps: Vec<usize> = graph
+                .dependencies(index, &[EdgeKind::Depends])
+                .map(|edge| edge.to)
+                .collect();
+            deps.sort_unstable();
+            deps.dedup();
+            (index, deps)
+        })
+        .collect()
+}
+
+/// Writes a CycloneDX SBOM in JSON, with the packages of a
+/// [`DependencyGraph`] as components and their dependencies as the
+/// dependency graph. Returns the number of components written.
+///
+/// A component is referred to by its purl, which is not unique if the same
+/// package is found in several repositories, e.g. `core` and `core-testing`
+/// with `--sync`; the references of such components are suffixed with their
+/// repositories, e.g. `pkg:alpm/arch/bash@5.2.026-2?arch=x86_64#core`.
+pub fn write_cyclonedx<W: Write>(writer: &mut W, graph: &DependencyGraph) -> anyhow::Result<usize> {
+    let packages = graph.packages();
+    let purls: Vec<String> = packages
+        .iter()
+        .map(|pkg| purl(pkg.name, pkg.version, pkg.architecture))
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for purl in &purls {
+        *counts.entry(purl).or_default() += 1;
+    }
+    let bom_refs: Vec<String> = packages
+        .iter()
+        .zip(&purls)
+        .enumerate()
+        .map(
+            |(index, (pkg, purl))| match (counts[purl.as_str()], pkg.repository) {
+                (1, _) => purl.clone(),
+                (_, Some(repository)) => format!("{purl}#{repository}"),
+                (_, None) => format!("{purl}#{index}"),
+            },
+        )
+        .collect();
+
+    let components: Vec<Value> = packages
+        .iter()
+        .zip(purls.iter().zip(&bom_refs))
+        .map(|(pkg, (purl, bom_ref))| {
+            let mut component = json!({
+                "type": "library",
+                "bom-ref": bom_ref,
+                "name": pkg.name,
+                "version": pkg.version,
+                "purl": purl,
+            });
+            if let Some(description) = pkg.description {
+                component["description"] = json!(description);
+            }
+            if let Some(packager) = pkg.packager {
+                component["publisher"] = json!(packager);
+            }
+            let licenses = cyclonedx_licenses(&pkg.licenses);
+            if !licenses.is_empty() {
+                component["licenses"] = json!(licenses);
+            }
+            let hashes: Vec<Value> = [("SHA-256", pkg.sha_256_sum), ("MD5", pkg.md5_sum)]
+                .into_iter()
+                .filter_map(|(alg, sum)| sum.map(|sum| json!({ "alg": alg, "content": sum })))
+                .collect();
+            if !hashes.is_empty() {
+                component["hashes"] = json!(hashes);
+            }
+            if let Some(url) = pkg.url {
+                component["externalReferences"] = json!([{ "type": "website", "url": url }]);
+            }
+            component
+        })
+        .collect();
+
+    let dependencies: Vec<Value> = depends_on(graph)
+        .into_iter()
+        .map(|(index, deps)| {
+            json!({
+                "ref": bom_refs[index],
+                "dependsOn": deps.into_iter().map(|dep| &bom_refs[dep]).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+
+    let bom = json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": CYCLONEDX_SPEC_VERSION,
+        "version": 1,
+        "metadata": {
+            "timestamp": format_timestamp(std::time::SystemTime::now()),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": components,
+        "dependencies": dependencies,
+    });
+
+    serde_json::to_writer(&mut *writer, &bom)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(packages.len())
+}
+
+/// Returns the CycloneDX `licenses` of a package: the licenses in the SPDX
+/// license list by their `id`, and the others by their `name`. As CycloneDX
+/// does not mix the expressions with the other licenses, a package with a
+/// compound expression, e.g. `GPL-2.0-or-later OR MIT`, gets a single
+/// `expression` instead, with the unknown licenses as license references.
+fn cyclonedx_licenses(licenses: &[&str]) -> Vec<Value> {
+    let mut references = Vec::new();
+    let expressions: Vec<String> = licenses
+        .iter()
+        .map(|license| spdx_license_expression(license, &mut references))
+        .collect();
+    // a malformed expression is converted to a single license reference
+    let compound = expressions.iter().any(|x| x.contains(' '));
+    if !compound {
+        return licenses
+            .iter()
+            .map(|&license| match spdx_license_id(license) {
+                Some(id) if !id.starts_with("LicenseRef-") => json!({ "license": { "id": id } }),
+                _ => json!({ "license": { "name": license } }),
+            })
+            .collect();
+    }
+    vec![json!({ "expression": spdx_conjunction(&expressions) })]
+}
+
+/// The SPDX specification version of the generated documents.
+pub const SPDX_VERSION: &str = "SPDX-2.3";
+
+/// An SPDX document, from which both the tag-value and the JSON formats
+/// are written.
+struct SpdxDocument {
+    namespace: String,
+    created: String,
+    packages: Vec<SpdxPackage>,
+    /// `(package, dependency)` pairs of indices of the `DEPENDS_ON`
+    /// relationships.
+    relationships: Vec<(usize, usize)>,
+    /// `(license id, original license)` pairs of the license references that
+    /// are not in the SPDX license list.
+    extracted_licenses: Vec<(String, String)>,
+}
+
+struct SpdxPackage {
+    id: String,
+    name: String,
+    version: String,
+    supplier: String,
+    /// `(algorithm, checksum)` pairs.
+    checksums: Vec<(&'static str, String)>,
+    license_declared: String,
+    homepage: Option<String>,
+    summary: Option<String>,
+    purl: String,
+}
+
+impl SpdxDocument {
+    fn new(graph: &DependencyGraph) -> Self {
+        let packages = graph.packages();
+        let now = std::time::SystemTime::now();
+        let nanos = now
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        let namespace = format!(
+            "https://spdx.org/spdxdocs/{}-{}-{}-{}",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION"),
+            std::process::id(),
+            nanos
+        );
+
+        let relationships = depends_on(graph)
+            .into_iter()
+            .flat_map(|(index, deps)| deps.into_iter().map(move |dep| (index, dep)))
+            .collect();
+
+        let mut extracted_licenses = Vec::new();
+        let packages = packages
+            .iter()
+            .enumerate()
+            .map(|(index, pkg)| {
+                let licenses: Vec<String> = pkg
+                    .licenses
+                    .iter()
+                    .map(|license| spdx_license_expression(license, &mut extracted_licenses))
+                    .collect();
+                SpdxPackage {
+                    id: format!("SPDXRef-Package-{}-{}", spdx_idstring(pkg.name), index),
+                    name: pkg.name.to_string(),
+                    version: pkg.version.to_string(),
+                    supplier: pkg.packager.map_or("NOASSERTION".into(), spdx_supplier),
+                    checksums: [("SHA256", pkg.sha_256_sum), ("MD5", pkg.md5_sum)]
+                        .into_iter()
+                        .filter_map(|(alg, sum)| sum.map(|sum| (alg, sum.to_string())))
+                        .collect(),
+                    license_declared: match licenses.is_empty() {
+                        true => "NOASSERTION".into(),
+                        false => spdx_conjunction(&licenses),
+                    },
+                    homepage: pkg.url.map(String::from),
+                    summary: pkg.description.map(String::from),
+                    purl: purl(pkg.name, pkg.version, pkg.architecture),
+                }
+            })
+            .collect();
+
+        Self {
+            namespace,
+            created: format_timestamp(now),
+            packages,
+            relationships,
+            extracted_licenses,
+        }
+    }
+}
+
+/// The identifiers of the [SPDX license list], one per line.
+///
+/// [SPDX license list]: https://spdx.org/licenses/
+const SPDX_LICENSE_IDS: &str = include_str!("spdx-license-ids.txt");
+
+/// The identifiers of the [SPDX license exceptions], one per line.
+///
+/// [SPDX license exceptions]: https://spdx.org/licenses/exceptions-index.html
+const SPDX_EXCEPTION_IDS: &str = include_str!("spdx-exception-ids.txt");
+
+/// Finds an identifier in a list, case-insensitively as SPDX does, and
+/// returns its canonical form.
+fn spdx_listed(ids: &'static str, id: &str) -> Option<&'static str> {
+    ids.lines().find(|x| x.eq_ignore_ascii_case(id))
+}
+
+/// Returns the canonical form of an SPDX license identifier, with the `+`
+/// suffix kept, or [`None`] if it is not in the SPDX license list. The
+/// license references, e.g. `LicenseRef-foo`, are kept as they are.
+fn spdx_license_id(license: &str) -> Option<String> {
+    if license.starts_with("LicenseRef-") && spdx_idstring(license) == license {
+        return Some(license.to_string());
+    }
+    let (id, plus) = match license.strip_suffix('+') {
+        Some(id) => (id, "+"),
+        None => (license, ""),
+    };
+    spdx_listed(SPDX_LICENSE_IDS, id).map(|id| format!("{id}{plus}"))
+}
+
+/// Joins the license expressions of a package with `AND`, with the compound
+/// expressions parenthesized.
+fn spdx_conjunction(expressions: &[String]) -> String {
+    match expressions {
+        [expression] => expression.clone(),
+        expressions => expressions
+            .iter()
+            .map(|x| match x.contains(' ') {
+                true => format!("({x})"),
+                false => x.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join(" AND "),
+    }
+}
+
+/// Converts a license of a package to an SPDX license expression. The
+/// compound expressions, with `AND`, `OR`, `WITH` and parentheses, are kept,
+/// while the licenses that are not in the SPDX license list, e.g. the legacy
+/// `GPL` or `custom:foo`, are converted to license references, which are
+/// recorded in `extracted_licenses`. A license that is not a well-formed
+/// expression, or with an unknown exception, is converted to a license
+/// reference as a whole.
+fn spdx_license_expression(
+    license: &str,
+    extracted_licenses: &mut Vec<(String, String)>,
+) -> String {
+    let mut reference = |license: &str| {
+        let id = format!("LicenseRef-{}", spdx_idstring(license));
+        if !extracted_licenses.iter().any(|(x, _)| x == &id) {
+            extracted_licenses.push((id.clone(), license.to_string()));
+        }
+        id
+    };
+
+    // the tokens of the expression, with the unknown licenses as errors
+    let spaced = license.replace('(', " ( ").replace(')', " ) ");
+    let mut tokens: Vec<Result<String, &str>> = Vec::new();
+    let (mut operand, mut depth) = (true, 0);
+    // whether an exception follows, and whether `WITH` may follow
+    let (mut exception, mut licensed) = (false, false);
+    let mut well_formed = true;
+    for token in spaced.split_whitespace() {
+        match token {
+            "(" if operand => depth += 1,
+            ")" if !operand && depth > 0 => depth -= 1,
+            "AND" | "OR" if !operand => operand = true,
+            "WITH" if licensed => (operand, exception) = (true, true),
+            "(" | ")" | "AND" | "OR" | "WITH" => well_formed = false,
+            id if exception => match spdx_listed(SPDX_EXCEPTION_IDS, id) {
+                Some(id) => {
+                    tokens.push(Ok(id.to_string()));
+                    (operand, exception, licensed) = (false, false, false);
+                    continue;
+                }
+                None => well_formed = false,
+            },
+            id if operand => {
+                tokens.push(spdx_license_id(id).ok_or(id));
+                (operand, licensed) = (false, true);
+                continue;
+            }
+            _ => well_formed = false,
+        }
+        if !well_formed {
+            break;
+        }
+        licensed = false;
+        tokens.push(Ok(token.to_string()));
+    }
+    if !well_formed || operand || depth > 0 {
+        return reference(license);
+    }
+
+    let tokens: Vec<String> = tokens
+        .into_iter()
+        .map(|token| token.unwrap_or_else(&mut reference))
+        .collect();
+    tokens.join(" ").replace("( ", "(").replace(" )", ")")
+}
+
+/// Replaces the characters not allowed in an SPDX identifier with `-`.
+fn spdx_idstring(string: &str) -> String {
+    string
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect()
+}
+
+/// Converts a packager, e.g. `Name <email>`, to an SPDX supplier, e.g.
+/// `Person: Name (email)`.
+fn spdx_supplier(packager: &str) -> String {
+    match packager
+        .trim()
+        .strip_suffix('>')
+        .and_then(|x| x.split_once('<'))
+    {
+        Some((name, email)) => format!("Person: {} ({})", name.trim(), email.trim()),
+        None => format!("Person: {}", packager.trim()),
+    }
+}
+
+/// Writes an SPDX document in JSON, with the packages of a
+/// [`DependencyGraph`] and their `DEPENDS_ON` relationships. Returns the
+/// number of packages written.
+pub fn write_spdx_json<W: Write>(writer: &mut W, graph: &DependencyGraph) -> anyhow::Result<usize> {
+    let doc = SpdxDocument::new(graph);
+
+    let spdx_packages: Vec<Value> = doc
+        .packages
+        .iter()
+        .map(|pkg| {
+            let mut package = json!({
+                "SPDXID": pkg.id,
+                "name": pkg.name,
+                "versionInfo": pkg.version,
+                "supplier": pkg.supplier,
+                "downloadLocation": "NOASSERTION",
+                "filesAnalyzed": false,
+                "checksums": pkg.checksums.iter().map(|(alg, sum)| json!({
+                    "algorithm": alg,
+                    "checksumValue": sum,
+                })).collect::<Vec<_>>(),
+                "licenseConcluded": "NOASSERTION",
+                "licenseDeclared": pkg.license_declared,
+                "copyrightText": "NOASSERTION",
+                "externalRefs": [{
+                    "referenceCategory": "PACKAGE-MANAGER",
+                    "referenceType": "purl",
+                    "referenceLocator": pkg.purl,
+                }],
+            });
+            if let Some(homepage) = &pkg.homepage {
+                package["homepage"] = json!(homepage);
+            }
+            if let Some(summary) = &pkg.summary {
+                package["summary"] = json!(summary);
+            }
+            package
+        })
+        .collect();
+
+    let relationships: Vec<Value> = doc
+        .packages
+        .iter()
+        .map(|pkg| ("SPDXRef-DOCUMENT", "DESCRIBES", &pkg.id))
+        .chain(doc.relationships.iter().map(|&(pkg, dep)| {
+            (
+                doc.packages[pkg].id.as_str(),
+                "DEPENDS_ON",
+                &doc.packages[dep].id,
+            )
+        }))
+        .map(|(element, relationship, related)| {
+            json!({
+                "spdxElementId": element,
+                "relationshipType": relationship,
+                "relatedSpdxElement": related,
+            })
+        })
+        .collect();
+
+    let mut spdx = json!({
+        "spdxVersion": SPDX_VERSION,
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": env!("CARGO_PKG_NAME"),
+        "documentNamespace": doc.namespace,
+        "creationInfo": {
+            "created": doc.created,
+            "creators": [format!("Tool: {}-{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    });
+    if !doc.extracted_licenses.is_empty() {
+        spdx["hasExtractedLicensingInfos"] = doc
+            .extracted_licenses
+            .iter()
+            .map(|(id, license)| {
+                json!({
+                    "licenseId": id,
+                    "name": license,
+                    "extractedText": license,
+                })
+            })
+            .collect();
+    }
+
+    serde_json::to_writer(&mut *writer, &spdx)?;
+    writeln!(writer)?;
+    writer.flush()?;
+    Ok(doc.packages.len())
+}
+
+/// Writes an SPDX document in the tag-value format, with the packages of a
+/// [`DependencyGraph`] and their `DEPENDS_ON` relationships. Returns the
+/// number of packages written.
+pub fn write_spdx_tag_value<W: Write>(
+    writer: &mut W,
+    graph: &DependencyGraph,
+) -> anyhow::Result<usize> {
+    let doc = SpdxDocument::new(graph);
+    let text = |string: &str| format!("<text>{}</text>", string.replace("</text>", ""));
+
+    writeln!(writer, "SPDXVersion: {SPDX_VERSION}")?;
+    writeln!(writer, "DataLicense: CC0-1.0")?;
+    writeln!(writer, "SPDXID: SPDXRef-DOCUMENT")?;
+    writeln!(writer, "DocumentName: {}", env!("CARGO_PKG_NAME"))?;
+    writeln!(writer, "DocumentNamespace: {}", doc.namespace)?;
+    writeln!(
+        writer,
+        "Creator: Tool: {}-{}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )?;
+    writeln!(writer, "Created: {}", doc.created)?;
+
+    for pkg in &doc.packages {
+        writeln!(writer)?;
+        writeln!(writer, "##### Package: {}", pkg.name)?;
+        writeln!(writer)?;
+        writeln!(writer, "PackageName: {}", pkg.name)?;
+        writeln!(writer, "SPDXID: {}", pkg.id)?;
+        writeln!(writer, "PackageVersion: {}", pkg.version)?;
+        writeln!(writer, "PackageSupplier: {}", pkg.supplier)?;
+        writeln!(writer, "PackageDownloadLocation: NOASSERTION")?;
+        writeln!(writer, "FilesAnalyzed: false")?;
+        for (alg, sum) in &pkg.checksums {
+            writeln!(writer, "PackageChecksum: {alg}: {sum}")?;
+        }
+        if let Some(homepage) = &pkg.homepage {
+            writeln!(writer, "PackageHomePage: {homepage}")?;
+        }
+        writeln!(writer, "PackageLicenseConcluded: NOASSERTION")?;
+        writeln!(writer, "PackageLicenseDeclared: {}", pkg.license_declared)?;
+        writeln!(writer, "PackageCopyrightText: NOASSERTION")?;
+        if let Some(summary) = &pkg.summary {
+            writeln!(writer, "PackageSummary: {}", text(summary))?;
+        }
+        writeln!(writer, "ExternalRef: PACKAGE-MANAGER purl {}", pkg.purl)?;
+    }
+
+    writeln!(writer)?;
+    for pkg in &doc.packages {
+        writeln!(
+            writer,
+            "Relationship: SPDXRef-DOCUMENT DESCRIBES {}",
+            pkg.id
+        )?;
+    }
+    for &(pkg, dep) in &doc.relationships {
+        writeln!(
+            writer,
+            "Relationship: {} DEPENDS_ON {}",
+            doc.packages[pkg].id, doc.packages[dep].id
+        )?;
+    }
+
+    for (id, license) in &doc.extracted_licenses {
+        writeln!(writer)?;
+        writeln!(writer, "LicenseID: {id}")?;
+        writeln!(writer, "ExtractedText: {}", text(license))?;
+        writeln!(writer, "LicenseName: {license}")?;
+    }
+
+    writer.flush()?;
+    Ok(doc.packages.len())
+}
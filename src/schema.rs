@@ -0,0 +1,255 @@
+//! A module that describes the format of the `pacjump` output, with a
+//! [JSON Schema] generated from the Rust types, and an optional [`Envelope`]
+//! which carries the schema version and the provenance of a dump.
+//!
+//! [JSON Schema]: https://json-schema.org
+
+use schemars::schema::{RootSchema, Schema, SchemaObject, SingleOrVec};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::info::{OwnedPackageInfo, PackageInfo};
+use crate::output::format_timestamp;
+use crate::Session;
+
+/// The version of the output format; this is bumped whenever a field of the
+/// output is renamed or removed, or changes its type.
+pub const SCHEMA_VERSION: &str = "1";
+
+/// A dump of packages, wrapped with the information on how and where it is
+/// generated.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct Envelope<T> {
+    /// The [`SCHEMA_VERSION`] of the dump.
+    pub schema_version: String,
+    pub pacjump_version: String,
+    /// The `RootDir` of the inspected system.
+    pub root: String,
+    /// The `DBPath` of the inspected system.
+    pub dbpath: String,
+    /// The registered sync repositories, in order.
+    pub repos: Vec<String>,
+    /// The generation time of the dump, as an RFC 3339 timestamp in UTC.
+    pub generated_at: String,
+    pub packages: Vec<T>,
+}
+
+impl<T> Envelope<T> {
+    /// Wraps the packages generated from a [`Session`].
+    pub fn new(session: &Session, packages: Vec<T>) -> Self {
+        let conf = session.conf();
+        Self {
+            schema_version: SCHEMA_VERSION.into(),
+            pacjump_version: env!("CARGO_PKG_VERSION").into(),
+            root: conf.root_dir.clone(),
+            dbpath: conf.db_path.clone(),
+            repos: conf.repo_list().map(String::from).collect(),
+            generated_at: format_timestamp(std::time::SystemTime::now()),
+            packages,
+        }
+    }
+}
+
+// the doc comment becomes the description of the schema
+/// The output of `pacjump` in JSON: either a bare list of packages, or the
+/// list wrapped in an envelope.
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum Dump<P> {
+    Packages(Vec<P>),
+    Envelope(Envelope<P>),
+    /// An envelope that is not readable, e.g. of another schema version
+    #[schemars(skip)]
+    Unsupported {
+        schema_version: String,
+    },
+    /// A single package, i.e. a line of the `--ndjson` output
+    #[schemars(skip)]
+    Package(Box<P>),
+}
+
+/// Reads the packages back from a `pacjump` dump, which is either a JSON list
+/// of packages, an [`Envelope`], or the `--ndjson` output with one package
+/// per line. An object with a `schema_version` is read as an envelope, and
+/// any other object as a package, which needs at least a `name` and a
+/// `version`; the other fields missing from the dump, e.g. if it is generated
+/// with `--fields`, are left empty.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::schema::read_dump;
+/// let packages = read_dump("[]".as_bytes())?;
+/// assert!(packages.is_empty());
+///
+/// let envelope = r#"{
+///     "schema_version": "1", "pacjump_version": "0.1.0",
+///     "root": "/", "dbpath": "/var/lib/pacman/", "repos": ["core"],
+///     "generated_at": "2024-01-01T00:00:00Z", "packages": []
+/// }"#;
+/// assert!(read_dump(envelope.as_bytes())?.is_empty());
+///
+/// let err = read_dump(envelope.replace(r#""1""#, r#""0""#).as_bytes()).unwrap_err();
+/// assert_eq!(err.to_string(), "unsupported schema version of the dump: \"0\"");
+///
+/// let ndjson = r#"
+///     {"name": "foo", "version": "1.0-1"}
+///     {"name": "bar", "version": "2.0-1"}
+/// "#;
+/// assert_eq!(read_dump(ndjson.as_bytes())?[1].name, "bar");
+///
+/// assert!(read_dump(r#"{"name": 42}"#.as_bytes()).is_err());
+/// let err = read_dump(r#"[{"name": "foo"}]"#.as_bytes()).unwrap_err();
+/// assert_eq!(err.to_string(), "a package of the dump has no name or version");
+/// assert!(read_dump("{}".as_bytes()).is_err());
+///
+/// // an envelope of another version, even with the packages renamed
+/// let newer = envelope.replace(r#""1""#, r#""2""#).replace("packages", "pkgs");
+/// let err = read_dump(newer.as_bytes()).unwrap_err();
+/// assert_eq!(err.to_string(), "unsupported schema version of the dump: \"2\"");
+///
+/// let broken = envelope.replace("packages", "pkgs");
+/// assert_eq!(read_dump(broken.as_bytes()).unwrap_err().to_string(), "invalid envelope of the dump");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn read_dump<R: Read>(reader: R) -> anyhow::Result<Vec<OwnedPackageInfo>> {
+    let mut packages: Vec<OwnedPackageInfo> = Vec::new();
+    for dump in serde_json::Deserializer::from_reader(reader).into_iter() {
+        match dump? {
+            Dump::Packages(list) => packages.extend(list),
+            Dump::Envelope(Envelope { schema_version, .. })
+            | Dump::Unsupported { schema_version }
+                if schema_version != SCHEMA_VERSION =>
+            {
+                anyhow::bail!("unsupported schema version of the dump: {schema_version:?}")
+            }
+            Dump::Envelope(envelope) => packages.extend(envelope.packages),
+            Dump::Unsupported { .. } => anyhow::bail!("invalid envelope of the dump"),
+            Dump::Package(package) => packages.push(*package),
+        }
+    }
+    if packages
+        .iter()
+        .any(|x| x.name.is_empty() || x.version.is_empty())
+    {
+        anyhow::bail!("a package of the dump has no name or version");
+    }
+    Ok(packages)
+}
+
+/// Reads the packages back from a `pacjump` dump file; see [`read_dump`].
+pub fn read_dump_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<OwnedPackageInfo>> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .map_err(|err| anyhow::anyhow!("failed to read {}: {err}", path.display()))?;
+    read_dump(BufReader::new(file))
+        .map_err(|err| anyhow::anyhow!("failed to load {}: {err}", path.display()))
+}
+
+/// Generates the JSON Schema of the `pacjump` output in JSON, which
+/// includes the definitions of [`PackageInfo`], [`DepInfo`], [`ReverseDeps`]
+/// and [`PacList`]. The [`SCHEMA_VERSION`] is recorded as the `version`
+/// keyword of the schema.
+///
+/// [`DepInfo`]: crate::info::DepInfo
+/// [`ReverseDeps`]: crate::reverse_deps::ReverseDeps
+/// [`PacList`]: crate::info::PacList
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::schema::{dump_schema, SCHEMA_VERSION};
+/// let schema = serde_json::to_value(dump_schema())?;
+/// assert_eq!(schema["version"], SCHEMA_VERSION);
+///
+/// let package = &schema["definitions"]["PackageInfo"];
+/// assert!(package["properties"]["depends_on"].is_object());
+/// assert!(schema["definitions"]["DepInfo"].is_object());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn dump_schema() -> RootSchema {
+    let mut schema = schemars::schema_for!(Dump<PackageInfo>);
+    schema.schema.metadata().title = Some(format!("{} dump", env!("CARGO_PKG_NAME")));
+    schema
+        .schema
+        .extensions
+        .insert("version".into(), SCHEMA_VERSION.into());
+    schema
+}
+
+/// Checks that a field path, e.g. `depends_on.name`, names a field of
+/// [`PackageInfo`], as it is described by its JSON Schema; the subfields of
+/// a list are the fields of its items.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::schema::check_field_path;
+/// assert!(check_field_path("name").is_ok());
+/// assert!(check_field_path("depends_on.name").is_ok());
+/// assert!(check_field_path("sync_with.depends_on.version").is_ok());
+/// assert!(check_field_path("closure.depth").is_ok());
+///
+/// assert!(check_field_path("depends_on.nmae").is_err());
+/// assert!(check_field_path("name.length").is_err());
+/// assert!(check_field_path("licenses.id").is_err());
+/// ```
+///
+pub fn check_field_path(path: &str) -> anyhow::Result<()> {
+    let root = schemars::schema_for!(PackageInfo);
+    let mut schemas = vec![&root.schema];
+    for field in path.split('.') {
+        schemas = schemas
+            .into_iter()
+            .flat_map(|schema| resolve_schema(&root, schema))
+            .filter_map(|schema| schema.object.as_ref()?.properties.get(field))
+            .filter_map(|schema| match schema {
+                Schema::Object(schema) => Some(schema),
+                Schema::Bool(_) => None,
+            })
+            .collect();
+        if schemas.is_empty() {
+            anyhow::bail!("unknown field: {:?}", path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the schema, together with the schemas it stands for: the
+/// definition it refers to, its alternatives, e.g. of an `Option`, and the
+/// items of a list.
+fn resolve_schema<'s>(root: &'s RootSchema, schema: &'s SchemaObject) -> Vec<&'s SchemaObject> {
+    let mut targets = Vec::new();
+    if let Some(name) = schema
+        .reference
+        .as_deref()
+        .and_then(|x| x.strip_prefix("#/definitions/"))
+    {
+        targets.extend(root.definitions.get(name));
+    }
+    if let Some(subschemas) = &schema.subschemas {
+        targets.extend(
+            [&subschemas.all_of, &subschemas.any_of, &subschemas.one_of]
+                .into_iter()
+                .flatten()
+                .flatten(),
+        );
+    }
+    match schema.array.as_ref().and_then(|x| x.items.as_ref()) {
+        Some(SingleOrVec::Single(item)) => targets.push(item),
+        Some(SingleOrVec::Vec(items)) => targets.extend(items),
+        None => (),
+    }
+    let mut resolved = vec![schema];
+    for target in targets {
+        if let Schema::Object(target) = target {
+            resolved.extend(resolve_schema(root, target));
+        }
+    }
+    resolved
+}
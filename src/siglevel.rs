@@ -5,27 +5,24 @@
 //! crate. This is a minimal re-implementation of components necessary for
 //! `pacjump`.
 
-use crate::read_conf;
+use crate::conf::{PacmanConf, RepoConf};
 use alpm::SigLevel;
 
-/// Parses and updates a _single_ `SigLevel` line from the cli `pacman-conf`.
+/// Parses and updates a _single_ `SigLevel` token from `pacman.conf`.
 ///
 /// * `default`: [`SigLevel`] - the default [`SigLevel`] which would be
-///                             stacked onto by the newly parsed `siglevel`
-/// * `siglevel`: `&str` - the siglevel string to parse into a [`SigLevel`]
+///   stacked onto by the newly parsed `siglevel`
+/// * `siglevel`: `&str` - the siglevel token to parse into a [`SigLevel`]
 ///
-/// The `SigLevel` return of `pacman-conf` is a fine-grained subset of those
-/// allowed in `pacman.conf`. For example, `SigLevel = Required` in
-/// `pacman.conf` will be resolved to two SigLevel lines by `pacman-conf`:
-/// one is `PackageRequired`, and the other is `DatabaseRequired`. We are only
-/// committed to parsing this fined-grained subset returned from `pacman-conf`.
-/// In particular, plain `Required` without the `Package` or `Database` prefix
-/// would not be allowed, and the code will panic.
+/// All the forms accepted by pacman are supported. A token with the
+/// `Package` or `Database` prefix, e.g. `PackageRequired`, applies to
+/// packages or databases only; a token without the prefix, e.g. `Required`,
+/// applies to both of them. The fine-grained output of the cli `pacman-conf`
+/// is thus also supported.
 ///
-/// This is a re-implementation of the following pacman functions:
+/// This is a re-implementation of the following pacman function:
 ///
 /// - `process_siglevel`: <https://gitlab.archlinux.org/pacman/pacman/-/blob/master/src/pacman/conf.c>
-/// - `show_siglevel`: <https://gitlab.archlinux.org/pacman/pacman/-/blob/master/src/pacman/pacman-conf.c>
 ///
 /// ### Examples:
 ///
@@ -35,49 +32,92 @@ use alpm::SigLevel;
 /// #
 /// // when an empty string is passed, the siglevel is unmodified;
 /// // whitespace is ignored:
-/// let siglevel = process_siglevel(SigLevel::PACKAGE_OPTIONAL, "\n\t \n");
+/// let siglevel = process_siglevel(SigLevel::PACKAGE_OPTIONAL, "\n\t \n")?;
 /// assert_eq!(siglevel, SigLevel::PACKAGE_OPTIONAL);
 ///
 /// // demands that packages require a signature:
-/// let siglevel = process_siglevel(SigLevel::USE_DEFAULT, "PackageRequired");
+/// let siglevel = process_siglevel(SigLevel::USE_DEFAULT, "PackageRequired")?;
 /// assert_eq!(siglevel, SigLevel::PACKAGE);
+///
+/// // demands that both packages and databases require a signature:
+/// let siglevel = process_siglevel(SigLevel::USE_DEFAULT, "Required")?;
+/// assert_eq!(siglevel, SigLevel::PACKAGE | SigLevel::DATABASE);
+/// # Ok::<(), anyhow::Error>(())
 /// ```
 ///
-/// An unrecognized siglevel string would panic:
+/// An unrecognized siglevel string would result in an error:
 ///
-/// ```should_panic
+/// ```
 /// # use alpm::SigLevel;
 /// # use pacjump::siglevel::process_siglevel;
-/// process_siglevel(SigLevel::USE_DEFAULT, "NonExistentSigLevel"); // panic!
+/// let err = process_siglevel(SigLevel::USE_DEFAULT, "NonExistentSigLevel").unwrap_err();
+/// assert_eq!(err.to_string(), "invalid value for 'SigLevel': \"NonExistentSigLevel\"");
 ///
-/// // only fine-grained SigLevels are allowed:
-/// process_siglevel(SigLevel::USE_DEFAULT, "Required"); // panic!
+/// // the prefix must be followed by a valid value:
+/// assert!(process_siglevel(SigLevel::USE_DEFAULT, "Package").is_err());
 /// ```
 ///
-pub fn process_siglevel(default: SigLevel, siglevel: &str) -> SigLevel {
-    let slset = |sl: SigLevel| (default | sl) & !SigLevel::USE_DEFAULT;
-    let slunset = |sl: SigLevel| (default & !sl) & !SigLevel::USE_DEFAULT;
+pub fn process_siglevel(default: SigLevel, siglevel: &str) -> anyhow::Result<SigLevel> {
+    let original = siglevel.trim();
+    if original.is_empty() {
+        return Ok(default);
+    }
 
-    let package_trust_all = SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK;
-    let database_trust_all = SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK;
+    let (package, database, value) = if let Some(value) = original.strip_prefix("Package") {
+        (true, false, value)
+    } else if let Some(value) = original.strip_prefix("Database") {
+        (false, true, value)
+    } else {
+        (true, true, original)
+    };
 
-    match siglevel.trim() {
-        "PackageNever" => slunset(SigLevel::PACKAGE),
-        "PackageOptional" => slset(SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL),
-        "PackageRequired" => slset(SigLevel::PACKAGE) & !SigLevel::PACKAGE_OPTIONAL,
-        "PackageTrustedOnly" => slunset(package_trust_all),
-        "PackageTrustAll" => slset(package_trust_all),
-        "DatabaseNever" => slunset(SigLevel::DATABASE),
-        "DatabaseOptional" => slset(SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL),
-        "DatabaseRequired" => slset(SigLevel::DATABASE) & !SigLevel::DATABASE_OPTIONAL,
-        "DatabaseTrustedOnly" => slunset(database_trust_all),
-        "DatabaseTrustAll" => slset(database_trust_all),
-        "" => default,
-        x => panic!("failed to parse the signature level: {}", x),
-    }
+    // the (package, database) flags to be set and unset by each value
+    let none = SigLevel::empty();
+    let (set, unset) = match value {
+        "Never" => ((none, none), (SigLevel::PACKAGE, SigLevel::DATABASE)),
+        "Optional" => (
+            (
+                SigLevel::PACKAGE | SigLevel::PACKAGE_OPTIONAL,
+                SigLevel::DATABASE | SigLevel::DATABASE_OPTIONAL,
+            ),
+            (none, none),
+        ),
+        "Required" => (
+            (SigLevel::PACKAGE, SigLevel::DATABASE),
+            (SigLevel::PACKAGE_OPTIONAL, SigLevel::DATABASE_OPTIONAL),
+        ),
+        "TrustedOnly" => (
+            (none, none),
+            (
+                SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK,
+                SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK,
+            ),
+        ),
+        "TrustAll" => (
+            (
+                SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK,
+                SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK,
+            ),
+            (none, none),
+        ),
+        _ => anyhow::bail!("invalid value for 'SigLevel': {:?}", original),
+    };
+    let select = |(pkg_flags, db_flags): (SigLevel, SigLevel)| -> SigLevel {
+        let mut flags = SigLevel::empty();
+        if package {
+            flags |= pkg_flags;
+        }
+        if database {
+            flags |= db_flags;
+        }
+        flags
+    };
+
+    Ok(((default | select(set)) & !select(unset)) & !SigLevel::USE_DEFAULT)
 }
 
-/// Updates the [`SigLevel`] recursively, from a multiline string.
+/// Updates the [`SigLevel`] successively, from a list of siglevel tokens,
+/// e.g. the value of a `SigLevel` directive split by whitespace.
 ///
 /// ### Examples:
 ///
@@ -85,40 +125,110 @@ pub fn process_siglevel(default: SigLevel, siglevel: &str) -> SigLevel {
 /// # use alpm::SigLevel;
 /// # use pacjump::siglevel::fold_siglevels;
 /// #
-/// let siglevel: String = [
+/// use SigLevel as Sig;
+/// let siglevel = fold_siglevels(Sig::USE_DEFAULT, [
 ///     "PackageRequired",
 ///     "PackageTrustedOnly",
 ///     "DatabaseOptional",
 ///     "DatabaseTrustedOnly",
-/// ].join("\n");
+/// ])?;
+/// assert_eq!(siglevel, Sig::PACKAGE | Sig::DATABASE | Sig::DATABASE_OPTIONAL);
 ///
-/// use SigLevel as Sig;
-/// let siglevel = fold_siglevels(Sig::USE_DEFAULT, siglevel);
+/// // the tokens as written in `pacman.conf`:
+/// let siglevel = fold_siglevels(Sig::USE_DEFAULT, "Required DatabaseOptional".split_whitespace())?;
 /// assert_eq!(siglevel, Sig::PACKAGE | Sig::DATABASE | Sig::DATABASE_OPTIONAL);
 ///
-/// // empty lines are ignored:
-/// let siglevel = fold_siglevels(Sig::DATABASE_OPTIONAL, "\n\n\n".into());
+/// // empty tokens are ignored:
+/// let siglevel = fold_siglevels(Sig::DATABASE_OPTIONAL, ["", "\n"])?;
 /// assert_eq!(siglevel, Sig::DATABASE_OPTIONAL);
 ///
-/// let siglevel = fold_siglevels(Sig::DATABASE_OPTIONAL, "".into());
+/// let siglevel = fold_siglevels(Sig::DATABASE_OPTIONAL, [""; 0])?;
 /// assert_eq!(siglevel, Sig::DATABASE_OPTIONAL);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn fold_siglevels<I>(default: SigLevel, siglevels: I) -> anyhow::Result<SigLevel>
+where
+    I: IntoIterator,
+    I::Item: AsRef<str>,
+{
+    siglevels.into_iter().try_fold(default, |siglevel, token| {
+        process_siglevel(siglevel, token.as_ref())
+    })
+}
+
+/// Converts a [`SigLevel`] back to its fine-grained textual form, which can
+/// be parsed again by [`fold_siglevels`]. An empty list is returned for
+/// [`SigLevel::USE_DEFAULT`].
+///
+/// This is a re-implementation of the following pacman function:
+///
+/// - `show_siglevel`: <https://gitlab.archlinux.org/pacman/pacman/-/blob/master/src/pacman/pacman-conf.c>
+///
+/// ### Examples:
+///
 /// ```
+/// # use alpm::SigLevel;
+/// # use pacjump::siglevel::{fold_siglevels, show_siglevel};
+/// #
+/// let siglevel = fold_siglevels(SigLevel::USE_DEFAULT, ["Required", "DatabaseOptional"])?;
+/// let tokens = show_siglevel(siglevel);
+/// assert_eq!(tokens, [
+///     "PackageRequired",
+///     "PackageTrustedOnly",
+///     "DatabaseOptional",
+///     "DatabaseTrustedOnly",
+/// ]);
+///
+/// // the textual form round-trips:
+/// assert_eq!(fold_siglevels(SigLevel::USE_DEFAULT, tokens)?, siglevel);
 ///
-pub fn fold_siglevels(default: SigLevel, siglevels: String) -> SigLevel {
-    siglevels
-        .split_terminator('\n')
-        .fold(default, process_siglevel)
+/// assert_eq!(show_siglevel(SigLevel::USE_DEFAULT), [""; 0]);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn show_siglevel(siglevel: SigLevel) -> Vec<&'static str> {
+    if siglevel == SigLevel::USE_DEFAULT {
+        return Vec::new();
+    }
+    let mut tokens = Vec::new();
+
+    if siglevel.contains(SigLevel::PACKAGE) {
+        tokens.push(match siglevel.contains(SigLevel::PACKAGE_OPTIONAL) {
+            true => "PackageOptional",
+            false => "PackageRequired",
+        });
+        tokens.push(match siglevel.contains(SigLevel::PACKAGE_UNKNOWN_OK) {
+            true => "PackageTrustAll",
+            false => "PackageTrustedOnly",
+        });
+    } else {
+        tokens.push("PackageNever");
+    }
+
+    if siglevel.contains(SigLevel::DATABASE) {
+        tokens.push(match siglevel.contains(SigLevel::DATABASE_OPTIONAL) {
+            true => "DatabaseOptional",
+            false => "DatabaseRequired",
+        });
+        tokens.push(match siglevel.contains(SigLevel::DATABASE_UNKNOWN_OK) {
+            true => "DatabaseTrustAll",
+            false => "DatabaseTrustedOnly",
+        });
+    } else {
+        tokens.push("DatabaseNever");
+    }
+
+    tokens
 }
 
-/// Finds the default SigLevel from `pacman.conf`; if it fails, fall back to
-/// the default [`SigLevel::USE_DEFAULT`].
-pub fn default_siglevel() -> SigLevel {
-    let siglevels = read_conf(["SigLevel"]).unwrap_or("".into());
-    fold_siglevels(SigLevel::USE_DEFAULT, siglevels)
+/// Finds the default SigLevel from the `[options]` of a [`PacmanConf`];
+/// if it is not set, fall back to the default [`SigLevel::USE_DEFAULT`].
+pub fn default_siglevel(conf: &PacmanConf) -> anyhow::Result<SigLevel> {
+    fold_siglevels(SigLevel::USE_DEFAULT, &conf.sig_level)
 }
 
-/// Finds the SigLevel of a repo; if it fails, fall back to the `default`.
-pub fn repo_siglevel(repo: &str, default: SigLevel) -> SigLevel {
-    let siglevels = read_conf(["--repo=", &repo, "SigLevel"]).unwrap_or("".into());
-    fold_siglevels(default, siglevels)
+/// Finds the SigLevel of a repo; if it is not set, fall back to the `default`.
+pub fn repo_siglevel(repo: &RepoConf, default: SigLevel) -> anyhow::Result<SigLevel> {
+    fold_siglevels(default, &repo.sig_level)
 }
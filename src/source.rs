@@ -0,0 +1,272 @@
+//! A module that abstracts over the sources of packages, so that the
+//! dependency analyses, e.g. [`recurse_dependencies`], run in the same way
+//! over the live alpm databases of a [`Session`], and over a dump generated
+//! earlier, possibly on another machine, with a [`DumpSource`].
+//!
+//! [`recurse_dependencies`]: crate::recurse_deps::recurse_dependencies
+
+use alpm::{vercmp, AlpmListMut};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::info::{DepInfo, OwnedPackageInfo, PackageInfo, SyncStatus};
+use crate::reverse_deps::ReverseDepsDatabase;
+use crate::schema::{read_dump, read_dump_file};
+use crate::{find_in_databases, PackageFilters, Session};
+
+/// A source of packages and their reverse dependencies.
+pub trait PackageSource {
+    /// Enumerates all packages that pass the filters.
+    fn packages<'a>(
+        &'a self,
+        pkg_filters: &'a PackageFilters,
+    ) -> Box<dyn Iterator<Item = PackageInfo<'a>> + 'a>;
+
+    /// Finds a package by its exact name.
+    fn find_package<'a>(
+        &'a self,
+        pkg_filters: &PackageFilters,
+        name: &str,
+    ) -> anyhow::Result<PackageInfo<'a>>;
+
+    /// Finds a package that satisfies a dependency, e.g. `glibc>=2.38`.
+    /// As in pacman, a package with the exact name is preferred over the
+    /// packages that provide the dependency.
+    fn find_satisfier<'a>(
+        &'a self,
+        pkg_filters: &PackageFilters,
+        dep: &DepInfo,
+    ) -> Option<PackageInfo<'a>>;
+
+    /// Returns the reverse dependencies of the packages.
+    fn reverse_deps(&self) -> &ReverseDepsDatabase;
+}
+
+impl PackageSource for Session {
+    fn packages<'a>(
+        &'a self,
+        pkg_filters: &'a PackageFilters,
+    ) -> Box<dyn Iterator<Item = PackageInfo<'a>> + 'a> {
+        Box::new(Session::packages(self, pkg_filters))
+    }
+
+    fn find_package<'a>(
+        &'a self,
+        pkg_filters: &PackageFilters,
+        name: &str,
+    ) -> anyhow::Result<PackageInfo<'a>> {
+        let pkg = find_in_databases(self.databases(pkg_filters).iter().copied(), name)?;
+        pkg_filters.generate_pkg_info(&self.handle, pkg, &self.reverse_deps)
+    }
+
+    fn find_satisfier<'a>(
+        &'a self,
+        pkg_filters: &PackageFilters,
+        dep: &DepInfo,
+    ) -> Option<PackageInfo<'a>> {
+        let mut_list = AlpmListMut::from_iter(self.databases(pkg_filters).iter().copied());
+        let pkg = mut_list.list().find_satisfier(dep.dep_string.as_str())?;
+        let pkg_info = pkg_filters
+            .generate_pkg_info(&self.handle, pkg, &self.reverse_deps)
+            .unwrap_or(pkg.into());
+        Some(pkg_info)
+    }
+
+    fn reverse_deps(&self) -> &ReverseDepsDatabase {
+        &self.reverse_deps
+    }
+}
+
+/// The packages loaded from a `pacjump` dump, with indices to resolve the
+/// dependencies among them. The reverse dependencies are regenerated from
+/// the packages in the dump, so that they reflect the dumped system; e.g.
+/// with a dump of `pacjump --all`, [`required_by`] lists the installed
+/// dependents only.
+///
+/// [`required_by`]: ReverseDepsDatabase::required_by
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::source::{DumpSource, PackageSource};
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "foo", "version": "1.0-1", "install_reason": "Explicit",
+///       "depends_on": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }],
+///       "provides": [] },
+///     { "name": "bash", "version": "5.2-1", "install_reason": "Depend",
+///       "depends_on": [],
+///       "provides": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+/// let filters = PackageFilters::parse_from(["pacjump"]);
+///
+/// // only the explicitly installed packages, unless `--all`:
+/// let names: Vec<_> = source.packages(&filters).map(|pkg| pkg.name).collect();
+/// assert_eq!(names, ["foo"]);
+///
+/// let foo = source.find_package(&filters, "foo")?;
+/// let sh = source.find_satisfier(&filters, &foo.depends_on[0]).unwrap();
+/// assert_eq!(sh.name, "bash");
+///
+/// // the reverse dependencies are keyed by the names of the dependencies:
+/// assert!(source.reverse_deps().required_by["sh"].contains("foo"));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub struct DumpSource {
+    packages: Vec<OwnedPackageInfo>,
+    /// The index of the first package of each name
+    names: HashMap<String, usize>,
+    /// The indices of the packages providing each name, in order
+    providers: HashMap<String, Vec<usize>>,
+    reverse_deps: ReverseDepsDatabase,
+}
+
+impl DumpSource {
+    /// Indexes the packages of a dump.
+    pub fn new(packages: Vec<OwnedPackageInfo>) -> Self {
+        let mut names = HashMap::new();
+        let mut providers: HashMap<_, Vec<_>> = HashMap::new();
+        for (idx, pkg) in packages.iter().enumerate() {
+            names.entry(pkg.name.clone()).or_insert(idx);
+            for provide in pkg.provides.iter() {
+                providers.entry(provide.name.clone()).or_default().push(idx);
+            }
+        }
+        let reverse_deps =
+            ReverseDepsDatabase::from_packages(packages.iter().map(PackageInfo::from));
+        Self {
+            packages,
+            names,
+            providers,
+            reverse_deps,
+        }
+    }
+
+    /// Reads a dump in any of the forms accepted by [`read_dump`].
+    pub fn from_reader<R: std::io::Read>(reader: R) -> anyhow::Result<Self> {
+        Ok(Self::new(read_dump(reader)?))
+    }
+
+    /// Reads a dump from a file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Ok(Self::new(read_dump_file(path)?))
+    }
+
+    /// Returns the packages of the dump, as they are loaded.
+    pub fn owned_packages(&self) -> &[OwnedPackageInfo] {
+        &self.packages
+    }
+
+    /// Borrows a package, with the regenerated reverse dependencies.
+    fn package_info(&self, idx: usize) -> PackageInfo<'_> {
+        PackageInfo::from(&self.packages[idx]).add_reverse_deps(&self.reverse_deps)
+    }
+}
+
+impl PackageSource for DumpSource {
+    fn packages<'a>(
+        &'a self,
+        pkg_filters: &'a PackageFilters,
+    ) -> Box<dyn Iterator<Item = PackageInfo<'a>> + 'a> {
+        Box::new(
+            (0..self.packages.len())
+                .filter(|&idx| {
+                    let pkg = &self.packages[idx];
+                    let explicit = &*pkg.install_reason == "Explicit";
+                    pkg_filters.selects(explicit)
+                        && pkg_filters.selects_locality(is_native(pkg))
+                        && pkg_filters.selects_sync_status(pkg.sync_status)
+                })
+                .map(|idx| self.package_info(idx)),
+        )
+    }
+
+    fn find_package<'a>(
+        &'a self,
+        _pkg_filters: &PackageFilters,
+        name: &str,
+    ) -> anyhow::Result<PackageInfo<'a>> {
+        match self.names.get(name) {
+            Some(&idx) => Ok(self.package_info(idx)),
+            None => anyhow::bail!("{:?} not found in the dump", name),
+        }
+    }
+
+    fn find_satisfier<'a>(
+        &'a self,
+        _pkg_filters: &PackageFilters,
+        dep: &DepInfo,
+    ) -> Option<PackageInfo<'a>> {
+        // the literal match first, and then the providers
+        let literal = self
+            .names
+            .get(dep.name)
+            .filter(|&&idx| satisfies_version(Some(&self.packages[idx].version), dep));
+        let provider = || {
+            self.providers.get(dep.name)?.iter().find(|&&idx| {
+                self.packages[idx]
+                    .provides
+                    .iter()
+                    .filter(|provide| provide.name == dep.name)
+                    .any(|provide| satisfies_version(provide.version.as_deref(), dep))
+            })
+        };
+        let &idx = literal.or_else(provider)?;
+        Some(self.package_info(idx))
+    }
+
+    fn reverse_deps(&self) -> &ReverseDepsDatabase {
+        &self.reverse_deps
+    }
+}
+
+/// Returns whether a dumped package is found in the sync databases, i.e. it
+/// is either a sync package, or a local package enriched with the sync info.
+/// Note that this cannot be told for a dump generated with `--plain`, where
+/// all local packages appear to be foreign.
+fn is_native(pkg: &OwnedPackageInfo) -> bool {
+    match pkg.sync_status {
+        Some(sync_status) => sync_status != SyncStatus::Foreign,
+        None => pkg.repository.as_deref() != Some("local") || pkg.sync_with.is_some(),
+    }
+}
+
+/// Checks whether a version, e.g. of a package or a provision, satisfies the
+/// version requirement of a dependency; the versions are compared by
+/// [`vercmp`]. An unversioned provision satisfies unversioned dependencies
+/// only, as in pacman.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::info::{DepInfo, OwnedDepInfo};
+/// # use pacjump::source::satisfies_version;
+/// let dep: OwnedDepInfo = serde_json::from_str(r#"{
+///     "dep_string": "glibc>=2.38", "name": "glibc", "depmod": "Ge", "version": "2.38"
+/// }"#)?;
+/// let dep = DepInfo::from(&dep);
+/// assert!(satisfies_version(Some("2.39-1"), &dep));
+/// assert!(satisfies_version(Some("2.38-2"), &dep));
+/// assert!(!satisfies_version(Some("2.9-1"), &dep));
+/// assert!(!satisfies_version(None, &dep));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn satisfies_version(version: Option<&str>, dep: &DepInfo) -> bool {
+    let (Some(version), Some(required)) = (version, dep.version) else {
+        return &*dep.depmod == "Any";
+    };
+    let order = vercmp(version, required);
+    match &*dep.depmod {
+        "Eq" => order == Ordering::Equal,
+        "Ge" => order != Ordering::Less,
+        "Le" => order != Ordering::Greater,
+        "Gt" => order == Ordering::Greater,
+        "Lt" => order == Ordering::Less,
+        _ => true,
+    }
+}
@@ -0,0 +1,79 @@
+//! A module that lists the pending upgrades of the installed packages, from
+//! the sync databases as they are synced, i.e. without refreshing them; this
+//! is similar to `pacman -Qu`.
+
+use serde::Serialize;
+
+use crate::info::{PackageInfo, SyncStatus};
+
+/// A pending upgrade of an installed package.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::info::{OwnedPackageInfo, PackageInfo};
+/// # use pacjump::upgrades::Upgrade;
+/// let pkg: OwnedPackageInfo = serde_json::from_str(r#"{
+///     "name": "foo", "version": "1.0-1", "repository": "local",
+///     "installed_size": 1000, "sync_status": "upgradable",
+///     "sync_with": {
+///         "name": "foo", "version": "1.1-1", "repository": "extra",
+///         "download_size": 300, "installed_size": 1200
+///     }
+/// }"#)?;
+/// let upgrade = Upgrade::new(&PackageInfo::from(&pkg)).unwrap();
+/// assert_eq!(upgrade.new_version, "1.1-1");
+/// assert_eq!(upgrade.installed_size_delta, 200);
+/// assert_eq!(upgrade.to_string(), "foo 1.0-1 -> 1.1-1");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Upgrade {
+    pub name: String,
+    /// The sync repository providing the upgrade.
+    pub repository: Option<String>,
+    pub old_version: String,
+    pub new_version: String,
+    /// The size of the new package to be downloaded.
+    pub download_size: i64,
+    pub old_installed_size: i64,
+    pub new_installed_size: i64,
+    /// The change of the installed size, which is negative if the package
+    /// shrinks.
+    pub installed_size_delta: i64,
+}
+
+impl Upgrade {
+    /// Finds the pending upgrade of an installed package, if its
+    /// [`SyncStatus`] is [`Upgradable`][SyncStatus::Upgradable].
+    pub fn new(pkg: &PackageInfo) -> Option<Self> {
+        if pkg.sync_status != Some(SyncStatus::Upgradable) {
+            return None;
+        }
+        // an upgradable package differs from its sync counterpart, so the
+        // local info is the base; see `PackageFilters::enrich_pkg_info`
+        let sync_pkg = pkg.sync_with.as_deref()?;
+        Some(Self {
+            name: pkg.name.to_string(),
+            repository: sync_pkg.repository.map(String::from),
+            old_version: pkg.version.to_string(),
+            new_version: sync_pkg.version.to_string(),
+            download_size: sync_pkg.download_size,
+            old_installed_size: pkg.installed_size,
+            new_installed_size: sync_pkg.installed_size,
+            installed_size_delta: sync_pkg.installed_size - pkg.installed_size,
+        })
+    }
+}
+
+impl std::fmt::Display for Upgrade {
+    /// Formats the upgrade as `pacman -Qu` does.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} -> {}",
+            self.name, self.old_version, self.new_version
+        )
+    }
+}
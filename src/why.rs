@@ -0,0 +1,221 @@
+//! A module that explains why a package is installed, by finding the chains
+//! of dependencies from the explicitly installed packages down to it. The
+//! dependencies are resolved to the installed satisfiers in a
+//! [`DependencyGraph`], so that each hop of a chain is labelled with the
+//! dependency string that it matches, e.g. `sh` for `bash`.
+
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use crate::graph::{DependencyGraph, Edge, EdgeKind};
+use crate::source::PackageSource;
+use crate::PackageFilters;
+
+/// The maximum number of chains enumerated with `--all-paths`.
+pub const MAX_CHAINS: usize = 10000;
+
+/// The maximum number of edges followed while enumerating the chains with
+/// `--all-paths`; the number of paths grows exponentially in a dense graph,
+/// even when few of them end at an explicitly installed package.
+pub const MAX_STEPS: usize = 1_000_000;
+
+/// A hop of a [`DependencyChain`].
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Hop {
+    pub name: String,
+    /// The dependency of the previous package that is satisfied by this
+    /// one; this is [`None`] for the first package of a chain.
+    pub dep_string: Option<String>,
+    /// Whether the dependency is optional.
+    pub optional: bool,
+}
+
+/// A chain of dependencies, from an explicitly installed package down to
+/// the queried package.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct DependencyChain {
+    pub hops: Vec<Hop>,
+}
+
+impl fmt::Display for DependencyChain {
+    /// Formats the chain as e.g. `foo -> bash (sh) -> glibc`, with the
+    /// dependency string shown when it differs from the package name, and
+    /// `?` marking an optional dependency.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, hop) in self.hops.iter().enumerate() {
+            if idx > 0 {
+                write!(f, " -{}> ", if hop.optional { "?" } else { "" })?;
+            }
+            write!(f, "{}", hop.name)?;
+            match &hop.dep_string {
+                Some(dep_string) if dep_string != &hop.name => write!(f, " ({dep_string})")?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Finds the chains of dependencies from the explicitly installed packages
+/// down to the package `name`; the optional dependencies are followed as
+/// well with `--optional`. A chain stops at the first explicitly installed
+/// package.
+///
+/// By default, one of the shortest chains is found for each explicitly
+/// installed package that pulls in `name`; with `all_paths`, all the chains
+/// without repeated packages are enumerated, up to [`MAX_CHAINS`] chains or
+/// [`MAX_STEPS`] edges followed. The chains are sorted by their lengths, and
+/// returned along with whether the enumeration is cut short.
+///
+/// ### Examples:
+///
+/// ```
+/// # use pacjump::source::DumpSource;
+/// # use pacjump::why::find_chains;
+/// # use pacjump::PackageFilters;
+/// # use clap::Parser;
+/// let dump = r#"[
+///     { "name": "app", "version": "1.0-1", "install_reason": "Explicit",
+///       "depends_on": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" },
+///                      { "dep_string": "glibc", "name": "glibc", "depmod": "Any" }] },
+///     { "name": "bash", "version": "5.2-1", "install_reason": "Depend",
+///       "depends_on": [{ "dep_string": "glibc", "name": "glibc", "depmod": "Any" }],
+///       "provides": [{ "dep_string": "sh", "name": "sh", "depmod": "Any" }] },
+///     { "name": "glibc", "version": "2.40-1", "install_reason": "Depend" },
+///     { "name": "viewer", "version": "1.0-1", "install_reason": "Explicit",
+///       "optional_deps": [{ "dep_string": "bash", "name": "bash", "depmod": "Any" }] }
+/// ]"#;
+/// let source = DumpSource::from_reader(dump.as_bytes())?;
+/// let filters = PackageFilters::parse_from(["pacjump", "--why=glibc"]);
+///
+/// let (chains, _) = find_chains(&source, &filters, "glibc", false)?;
+/// assert_eq!(chains.len(), 1);
+/// assert_eq!(chains[0].to_string(), "app -> glibc");
+///
+/// let (chains, truncated) = find_chains(&source, &filters, "glibc", true)?;
+/// let chains: Vec<_> = chains.iter().map(ToString::to_string).collect();
+/// assert_eq!(chains, ["app -> glibc", "app -> bash (sh) -> glibc"]);
+/// assert!(!truncated);
+///
+/// // the optional dependencies are followed with `--optional`:
+/// let filters = PackageFilters::parse_from(["pacjump", "--why=bash", "--optional"]);
+/// let (chains, _) = find_chains(&source, &filters, "bash", false)?;
+/// assert_eq!(chains[1].to_string(), "viewer -?> bash");
+/// assert!(find_chains(&source, &filters, "missing", false).is_err());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+///
+pub fn find_chains<S>(
+    source: &S,
+    pkg_filters: &PackageFilters,
+    name: &str,
+    all_paths: bool,
+) -> anyhow::Result<(Vec<DependencyChain>, bool)>
+where
+    S: PackageSource + ?Sized,
+{
+    let graph = DependencyGraph::new(source, pkg_filters);
+    let packages = graph.packages();
+    let Some(target) = graph.index_of(name) else {
+        anyhow::bail!("{:?} is not installed", name);
+    };
+    let kinds: &[EdgeKind] = match pkg_filters.optional {
+        true => &[EdgeKind::Depends, EdgeKind::OptDepends],
+        false => &[EdgeKind::Depends],
+    };
+    let dependents: Vec<Vec<&Edge>> = (0..graph.len())
+        .map(|idx| graph.dependents(idx, kinds).collect())
+        .collect();
+
+    let is_explicit = |idx: usize| &*packages[idx].install_reason == "Explicit";
+    // builds a chain from the edges between an explicit package and the
+    // target, starting from the edge to the target
+    let chain = |edges: &[&Edge]| {
+        let mut hops = Vec::new();
+        let mut next = Hop {
+            name: packages[target].name.to_string(),
+            dep_string: None,
+            optional: false,
+        };
+        for edge in edges {
+            next.dep_string = Some(edge.dep_string.clone());
+            next.optional = edge.kind == EdgeKind::OptDepends;
+            hops.push(next);
+            next = Hop {
+                name: packages[edge.from].name.to_string(),
+                dep_string: None,
+                optional: false,
+            };
+        }
+        hops.push(next);
+        hops.reverse();
+        DependencyChain { hops }
+    };
+
+    let mut chains = Vec::new();
+    let mut truncated = false;
+    match all_paths {
+        false => {
+            // breadth first, from the target up to the explicit packages,
+            // with the edge by which each package is reached, and its target
+            let mut via: HashMap<usize, Option<(usize, &Edge)>> = HashMap::from([(target, None)]);
+            let mut queue = VecDeque::from([target]);
+            while let Some(idx) = queue.pop_front() {
+                if is_explicit(idx) {
+                    // walks down from the explicit package to the target
+                    let mut edges = Vec::new();
+                    let mut current = idx;
+                    while let Some((next, edge)) = via[&current] {
+                        edges.push(edge);
+                        current = next;
+                    }
+                    edges.reverse();
+                    chains.push(chain(&edges));
+                    continue;
+                }
+                for &edge in &dependents[idx] {
+                    if let Entry::Vacant(entry) = via.entry(edge.from) {
+                        entry.insert(Some((idx, edge)));
+                        queue.push_back(edge.from);
+                    }
+                }
+            }
+        }
+        true => {
+            // depth first, with the edges of the current path and the
+            // positions of the next edges to try
+            let mut path: Vec<&Edge> = Vec::new();
+            let mut work = vec![(target, 0)];
+            let mut steps = 0;
+            while let Some(&(idx, next)) = work.last() {
+                if next == 0 && is_explicit(idx) {
+                    chains.push(chain(&path));
+                    if chains.len() >= MAX_CHAINS {
+                        truncated = true;
+                        break;
+                    }
+                } else if let Some(&edge) = dependents[idx].get(next) {
+                    steps += 1;
+                    if steps > MAX_STEPS {
+                        truncated = true;
+                        break;
+                    }
+                    work.last_mut().unwrap().1 += 1;
+                    let visited = edge.from == target || path.iter().any(|x| x.from == edge.from);
+                    if !visited {
+                        path.push(edge);
+                        work.push((edge.from, 0));
+                    }
+                    continue;
+                }
+                work.pop();
+                path.pop();
+            }
+        }
+    }
+    chains.sort_by_key(|chain| chain.hops.len());
+    Ok((chains, truncated))
+}